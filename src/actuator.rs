@@ -0,0 +1,264 @@
+/*! # Actuator
+
+Shapes the fin angle the controller asks for into something we can send to
+the hardware.
+
+The fins can't move instantly and can only turn so far, so each command is
+limited to how far the servo can slew in one control step and to the travel
+of the fins. The controller limits its output to the same range (so the
+integrator knows when it's pinned), and anything that still had to be limited
+is marked as saturated.
+
+The servo doesn't know about degrees, it wants a pulse width (or counts,
+depending on the servo board). A calibration table maps fin angle to servo
+position, interpolated in between. The result goes out in a `ROLL` message to
+the actuator, and is logged so we can see what the fins were really told to
+do.
+*/
+
+extern crate byteorder;
+
+use std::io::Cursor;
+use config;
+use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+
+/// Roll actuator message size (bytes)
+pub const SIZE_OF_ROLL: usize = 19;
+
+/// Roll actuator message name (ASCII: ROLL)
+pub const ROLL_NAME: [u8;4] = [82, 79, 76, 76];
+
+/// Servo position with the fins at neutral when there's no calibration table
+/// [µs of pulse width]
+const NEUTRAL_POSITION: f64 = 1500.0;
+
+/// Change in servo position per degree of fin angle when there's no
+/// calibration table [µs/deg]
+const POSITION_PER_DEGREE: f64 = 10.0;
+
+
+/// Map from fin angle to servo position.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::actuator;
+///
+/// let mut calibration = actuator::Calibration::new();
+/// calibration.add(15.0, 1700.0);
+/// calibration.add(-15.0, 1320.0);
+/// calibration.add(0.0, 1510.0);
+///
+/// assert_eq!(calibration.position(0.0), 1510);
+/// assert_eq!(calibration.position(7.5), 1605);
+///
+/// // Past the end of the table we stick with the last position
+/// assert_eq!(calibration.position(-20.0), 1320);
+///
+/// // And if the angle is nonsense the fins go to neutral
+/// assert_eq!(calibration.position(f64::NAN), 1510);
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Calibration {
+
+    /// Servo position at each fin angle [deg], in order of angle. With no
+    /// points the servo is assumed centred at 1500 µs, 10 µs/deg.
+    pub points: Vec<(f64, f64)>,
+}
+
+
+impl Calibration {
+
+    /// An empty table.
+    pub fn new() -> Calibration {
+        Default::default()
+    }
+
+    /// Add the servo position for a fin angle.
+    pub fn add(&mut self, angle: f64, position: f64) {
+        let index = self.points.iter().position(|&(at, _)| at > angle).unwrap_or(self.points.len());
+        self.points.insert(index, (angle, position));
+    }
+
+    /// Servo position for a fin angle, interpolated between the points
+    /// either side.
+    ///
+    /// The position never goes past the ends of the table (or what the
+    /// servo can be sent, with no table). An angle that isn't a number gives
+    /// the position for neutral (zero degrees).
+    pub fn position(&self, angle: f64) -> u16 {
+        let angle = if angle.is_finite() { angle } else { 0.0 };
+        let position = match self.points.iter().position(|&(at, _)| at > angle) {
+            None if self.points.is_empty() => NEUTRAL_POSITION + angle * POSITION_PER_DEGREE,
+            None => self.points[self.points.len() - 1].1,
+            Some(0) => self.points[0].1,
+            Some(after) => {
+                let (x0, p0) = self.points[after - 1];
+                let (x1, p1) = self.points[after];
+                p0 + (angle - x0) / (x1 - x0) * (p1 - p0)
+            },
+        };
+
+        // The table is in order of angle, not position, so the ends could be
+        // either way round
+        let (min, max) = self.points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &(_, p)| (min.min(p), max.max(p)));
+        let (min, max) = if self.points.is_empty() { (0.0, u16::MAX as f64) } else { (min, max) };
+        position.clamp(min, max).round() as u16
+    }
+}
+
+
+/// What we sent to the roll actuator (`ROLL` message).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Roll {
+
+    /// Fin angle the controller asked for [deg]
+    pub requested: f64,
+
+    /// Fin angle after slew and travel limits [deg]
+    pub angle: f64,
+
+    /// Servo position for that angle (pulse width or counts)
+    pub position: u16,
+
+    /// True if the angle was limited
+    pub saturated: bool,
+}
+
+
+impl Roll {
+
+    /// Read a roll message back from bytes.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> Roll {
+        let mut message = Cursor::new(message_buffer);
+
+        Roll {
+            requested: message.read_f64::<BigEndian>().unwrap(),
+            angle:     message.read_f64::<BigEndian>().unwrap(),
+            position:  message.read_u16::<BigEndian>().unwrap(),
+            saturated: message.read_u8().unwrap() != 0,
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_ROLL] {
+        let mut buffer = [0u8; SIZE_OF_ROLL];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_f64::<BigEndian>(self.requested).unwrap();
+            message.write_f64::<BigEndian>(self.angle).unwrap();
+            message.write_u16::<BigEndian>(self.position).unwrap();
+            message.write_u8(self.saturated as u8).unwrap();
+        }
+        buffer
+    }
+}
+
+
+/// Roll actuator output stage.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::actuator;
+///
+/// let mut fins: actuator::Actuator = Default::default();
+/// fins.slew_rate = 100.0;
+///
+/// // Can't get all the way there in one step
+/// let roll = fins.command(10.0, 0.01);
+/// assert_eq!(roll.angle, 1.0);
+/// assert!(roll.saturated);
+///
+/// // Nor past the end of travel
+/// fins.slew_rate = 0.0;
+/// let roll = fins.command(40.0, 0.01);
+/// assert_eq!(roll.angle, fins.travel_max);
+/// assert_eq!(roll.position, 1650);
+/// ```
+pub struct Actuator {
+
+    /// Fastest the fins can turn [deg/s]. Zero means no limit.
+    pub slew_rate: f64,
+
+    /// Furthest the fins can turn one way [deg]
+    pub travel_min: f64,
+
+    /// Furthest the fins can turn the other way [deg]
+    pub travel_max: f64,
+
+    /// Fin angle to servo position
+    pub calibration: Calibration,
+
+    /// Where we last told the fins to go [deg]
+    pub angle: f64,
+}
+
+
+impl Default for Actuator {
+    fn default () -> Actuator {
+        Actuator::new(&Default::default())
+    }
+}
+
+
+impl Actuator {
+
+    /// An actuator with settings from the configuration, starting at
+    /// neutral.
+    pub fn new(config: &config::Actuator) -> Actuator {
+        Actuator {
+            slew_rate: config.slew_rate,
+            travel_min: config.travel_min,
+            travel_max: config.travel_max,
+            calibration: config.calibration.clone(),
+            angle: 0.0_f64.clamp(config.travel_min, config.travel_max),
+        }
+    }
+
+    /// Range of fin angles we can get to in one step.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **dt**: Time since the last step [s]
+    ///
+    /// ## Returns:
+    ///
+    /// The smallest and largest angle [deg].
+    pub fn reach(&self, dt: f64) -> (f64, f64) {
+        if self.slew_rate <= 0.0 {
+            return (self.travel_min, self.travel_max);
+        }
+        let step = self.slew_rate * dt;
+        (self.travel_min.max(self.angle - step), self.travel_max.min(self.angle + step))
+    }
+
+    /// Move the fins towards an angle.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **angle**: Fin angle the controller asked for [deg]
+    /// - **dt**: Time since the last step [s]
+    ///
+    /// ## Returns:
+    ///
+    /// The message for the actuator.
+    pub fn command(&mut self, angle: f64, dt: f64) -> Roll {
+        let (min, max) = self.reach(dt);
+        self.angle = angle.clamp(min, max);
+
+        Roll {
+            requested: angle,
+            angle: self.angle,
+            position: self.calibration.position(self.angle),
+            saturated: self.angle != angle,
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Convert a flight computer log file into tables.
+//!
+//! Writes one file per message type, either CSV or JSON Lines, with values in
+//! SI units and timestamps in seconds from boot.
+
+extern crate rust_fc;
+
+use rust_fc::control;
+use rust_fc::log;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::time;
+
+const USAGE: &str = r#"
+Usage: fc-log [options] LOGFILE
+
+Convert a flight computer log file into one table per message type.
+
+Options:
+    --json          Write JSON Lines instead of CSV
+    --name NAME     Only convert this message type (can be given more than
+                    once, or as a comma separated list)
+    --from SECONDS  Skip messages before this time
+    --to SECONDS    Skip messages after this time
+    --output DIR    Where to write the tables (default: next to LOGFILE)
+
+Tables are named after the log and message type, like logfile-000.ADIS.csv
+Messages we can't decode get a table for each name and size, with their raw
+bytes, like logfile-000.GPS1-66.csv. A name that isn't all upper case letters
+and digits is written in hex.
+
+Times are in seconds from boot. Other columns are named after the fields of
+the message and are in the same units the flight computer uses: m, m/s,
+m/s/s, deg, deg/s, V, T and K, except times between packets in LINK
+messages and delays in LOOP messages, which are in µs, and rates, which are
+in Hz.
+"#;
+
+
+/// A single value in a table row
+enum Value {
+    Number(String),
+    Text(String),
+}
+
+
+// Seconds, with full nanosecond precision
+fn seconds(time: time::Duration) -> Value {
+    Value::Number(format!("{}.{:09}", time.as_secs(), time.subsec_nanos()))
+}
+
+// Same as above, from a count of nanoseconds
+fn nanoseconds(nanos: u64) -> Value {
+    Value::Number(format!("{}.{:09}", nanos / 1000000000, nanos % 1000000000))
+}
+
+fn number<T: ToString>(value: T) -> Value {
+    Value::Number(value.to_string())
+}
+
+
+// Column names and values for a message
+fn columns(message: &log::Message) -> Vec<(&'static str, Value)> {
+    match *message {
+        log::Message::Adis(ref adis) => vec![
+            ("vcc", number(adis.vcc)),
+            ("gyro_x", number(adis.gyro_x)),
+            ("gyro_y", number(adis.gyro_y)),
+            ("gyro_z", number(adis.gyro_z)),
+            ("acc_x", number(adis.acc_x)),
+            ("acc_y", number(adis.acc_y)),
+            ("acc_z", number(adis.acc_z)),
+            ("magn_x", number(adis.magn_x)),
+            ("magn_y", number(adis.magn_y)),
+            ("magn_z", number(adis.magn_z)),
+            ("temp", number(adis.temp)),
+        ],
+        log::Message::State(ref state) => vec![
+            ("state_time", nanoseconds(state.time)),
+            ("acc_up", number(state.acc_up)),
+            ("vel_up", number(state.vel_up)),
+            ("altitude", number(state.altitude)),
+            ("roll_rate", number(state.roll_rate)),
+            ("roll_angle", number(state.roll_angle)),
+        ],
+        log::Message::Seqn(seqn) => vec![
+            ("sequence_number", number(seqn)),
+        ],
+        log::Message::SequenceError(ref seqe) => vec![
+            ("port", number(seqe.port)),
+            ("expected", number(seqe.expected)),
+            ("received", number(seqe.received)),
+        ],
+        log::Message::SequenceStats(ref seqs) => vec![
+            ("port", number(seqs.port)),
+            ("received", number(seqs.received)),
+            ("lost", number(seqs.lost)),
+            ("duplicates", number(seqs.duplicates)),
+            ("reordered", number(seqs.reordered)),
+            ("restarts", number(seqs.restarts)),
+        ],
+        log::Message::LinkStats(ref link) => vec![
+            ("port", number(link.port)),
+            ("packets", number(link.packets)),
+            ("rate", number(link.rate)),
+            ("gaps", number(link.gaps)),
+            ("lost", number(link.lost)),
+            ("duplicates", number(link.duplicates)),
+            ("unknown", number(link.unknown)),
+            ("unlogged", number(link.unlogged)),
+            ("interval_min", number(link.interval_min)),
+            ("interval_mean", number(link.interval_mean)),
+            ("interval_max", number(link.interval_max)),
+        ],
+        log::Message::SendStats(ref sends) => vec![
+            ("destination", number(sends.destination)),
+            ("packets", number(sends.packets)),
+            ("errors", number(sends.errors)),
+        ],
+        log::Message::Control(ref terms) => vec![
+            ("dt", number(terms.dt)),
+            ("kp", number(terms.gains.kp)),
+            ("ki", number(terms.gains.ki)),
+            ("kd", number(terms.gains.kd)),
+            ("mode", Value::Text(match terms.target { control::Target::RollRate(_) => "rate", control::Target::RollAngle(_) => "angle" }.to_string())),
+            ("target", number(match terms.target { control::Target::RollRate(target) | control::Target::RollAngle(target) => target })),
+            ("error", number(terms.error)),
+            ("proportional", number(terms.proportional)),
+            ("integral", number(terms.integral)),
+            ("derivative", number(terms.derivative)),
+            ("requested", number(terms.requested)),
+            ("output", number(terms.output)),
+            ("active", number(terms.active)),
+            ("enabled", number(terms.enabled)),
+            ("saturated", number(terms.saturated)),
+        ],
+        log::Message::Roll(ref roll) => vec![
+            ("requested", number(roll.requested)),
+            ("angle", number(roll.angle)),
+            ("position", number(roll.position)),
+            ("saturated", number(roll.saturated)),
+        ],
+        log::Message::LoopStats(ref stats) => vec![
+            ("messages", number(stats.messages)),
+            ("latency_min", number(stats.latency_min)),
+            ("latency_mean", number(stats.latency_mean)),
+            ("latency_max", number(stats.latency_max)),
+            ("timers", number(stats.timers)),
+            ("late_mean", number(stats.late_mean)),
+            ("late_max", number(stats.late_max)),
+        ],
+        log::Message::Restart(ref restart) => vec![
+            ("downtime", nanoseconds(restart.downtime)),
+            ("resumed_from", nanoseconds(restart.resumed_from)),
+        ],
+        log::Message::Shutdown(ref shutdown) => vec![
+            ("signal", number(shutdown.signal)),
+        ],
+        log::Message::RawPacket(ref raw) => vec![
+            ("address", Value::Text(raw.from.ip().to_string())),
+            ("port", number(raw.from.port())),
+            ("length", number(raw.length)),
+            ("data", Value::Text(raw.payload.iter().map(|b| format!("{:02x}", b)).collect())),
+        ],
+        log::Message::Unknown(ref bytes) => vec![
+            ("size", number(bytes.len())),
+            ("data", Value::Text(bytes.iter().map(|b| format!("{:02x}", b)).collect())),
+        ],
+    }
+}
+
+
+// A message name, and the size for messages we can't decode
+type Table = ([u8; 4], Option<usize>);
+
+
+// What a table is called in its file name. The name goes in as it is if it's
+// upper case letters and digits, like a proper message name, otherwise in hex
+// so it can't do anything odd to the path.
+fn table_name((name, size): Table) -> String {
+    let name = if name.iter().all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        String::from_utf8_lossy(&name).into_owned()
+    }
+    else {
+        name.iter().map(|b| format!("{:02X}", b)).collect()
+    };
+    match size {
+        Some(size) => format!("{}-{}", name, size),
+        None => name,
+    }
+}
+
+
+// Write a row as CSV, with a header line first if this is a new table
+fn write_csv(out: &mut dyn Write, first: bool, row: &[(&'static str, Value)]) -> std::io::Result<()> {
+    if first {
+        let names: Vec<&str> = row.iter().map(|&(name, _)| name).collect();
+        writeln!(out, "{}", names.join(","))?;
+    }
+    let values: Vec<&str> = row.iter().map(|(_, value)| match *value {
+        Value::Number(ref n) => n.as_str(),
+        Value::Text(ref t) => t.as_str(),
+    }).collect();
+    writeln!(out, "{}", values.join(","))
+}
+
+
+// Write a row as a JSON object on its own line
+fn write_json(out: &mut dyn Write, row: &[(&'static str, Value)]) -> std::io::Result<()> {
+    let fields: Vec<String> = row.iter().map(|&(name, ref value)| match *value {
+        Value::Number(ref n) if n.parse::<f64>().map(|n| n.is_finite()).unwrap_or(false) => format!("\"{}\":{}", name, n),
+        Value::Number(_) => format!("\"{}\":null", name),
+        Value::Text(ref t) => format!("\"{}\":\"{}\"", name, t),
+    }).collect();
+    writeln!(out, "{{{}}}", fields.join(","))
+}
+
+
+fn fail(message: &str) -> ! {
+    eprintln!("fc-log: {}", message);
+    eprint!("{}", USAGE);
+    process::exit(2);
+}
+
+
+fn parse_seconds(value: Option<String>) -> time::Duration {
+    match value.as_ref().map(|v| v.parse::<f64>()) {
+        Some(Ok(s)) if s >= 0.0 => time::Duration::new(s as u64, ((s - s.floor()) * 1e9) as u32),
+        _ => fail("expected a time in seconds"),
+    }
+}
+
+
+fn main() {
+
+    let mut json = false;
+    let mut names: Vec<[u8; 4]> = Vec::new();
+    let mut from = time::Duration::new(0, 0);
+    let mut to: Option<time::Duration> = None;
+    let mut output: Option<String> = None;
+    let mut path: Option<String> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => { json = true; },
+            "--name" => {
+                let value = args.next().unwrap_or_else(|| fail("--name needs a message name"));
+                for name in value.split(',') {
+                    let bytes = name.as_bytes();
+                    if bytes.len() != 4 {
+                        fail(&format!("'{}' is not a four character message name", name));
+                    }
+                    names.push([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+            },
+            "--from" => { from = parse_seconds(args.next()); },
+            "--to" => { to = Some(parse_seconds(args.next())); },
+            "--output" => { output = Some(args.next().unwrap_or_else(|| fail("--output needs a directory"))); },
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with("--") => fail(&format!("unknown option {}", arg)),
+            _ if path.is_none() => { path = Some(arg); },
+            _ => fail("only one log file at a time"),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| fail("no log file given"));
+    let reader = match log::Reader::open(&path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("fc-log: {}: {}", path, e);
+            process::exit(1);
+        },
+    };
+
+    // Tables go next to the log unless we're told otherwise
+    let log_path = Path::new(&path);
+    let directory = match output {
+        Some(ref dir) => Path::new(dir).to_path_buf(),
+        None => log_path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+    };
+    let stem = log_path.file_name().unwrap().to_string_lossy().into_owned();
+    let extension = if json { "jsonl" } else { "csv" };
+
+    // Each message type gets its own table, or None if we couldn't open it
+    let mut tables: HashMap<Table, Option<BufWriter<File>>> = HashMap::new();
+
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                // A crash can leave a partial record at the end, everything
+                // before it is still good.
+                eprintln!("fc-log: {}: {}", path, e);
+                break;
+            },
+        };
+
+        if (!names.is_empty() && !names.contains(&record.name)) || record.time < from {
+            continue;
+        }
+        if let Some(to) = to {
+            if record.time > to {
+                continue;
+            }
+        }
+
+        let mut row = vec![("time", seconds(record.time))];
+        row.extend(columns(&record.message));
+
+        // Messages we can't decode only line up with others of the same size
+        let key = match record.message {
+            log::Message::Unknown(ref bytes) => (record.name, Some(bytes.len())),
+            _ => (record.name, None),
+        };
+
+        let first = !tables.contains_key(&key);
+        if first {
+            let table = directory.join(format!("{}.{}.{}", stem, table_name(key), extension));
+            let file = match File::create(&table) {
+                Ok(file) => Some(BufWriter::new(file)),
+                Err(e) => {
+                    // Carry on with the rest of the tables
+                    eprintln!("fc-log: {}: {}", table.display(), e);
+                    None
+                },
+            };
+            tables.insert(key, file);
+        }
+        let out = match tables.get_mut(&key).unwrap() {
+            Some(out) => out,
+            None => { continue; },
+        };
+
+        let result = if json { write_json(out, &row) } else { write_csv(out, first, &row) };
+        if let Err(e) = result {
+            eprintln!("fc-log: {}", e);
+            process::exit(1);
+        }
+    }
+
+    // Say something went wrong if we had to skip a table
+    let skipped = tables.values().any(|table| table.is_none());
+    for mut table in tables.into_values().flatten() {
+        if let Err(e) = table.flush() {
+            eprintln!("fc-log: {}", e);
+            process::exit(1);
+        }
+    }
+    if skipped {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,143 @@
+//! Print a summary of a flight from a flight computer log file.
+
+extern crate rust_fc;
+
+use rust_fc::{log, report};
+use std::env;
+use std::process;
+use std::time;
+
+const USAGE: &str = r#"
+Usage: fc-report [--json] [--imu-port PORT] LOGFILE
+
+Print a summary of a flight: maximum altitude, velocity and acceleration,
+apogee, burn time, and packet counts.
+
+Options:
+    --json            Write the summary as JSON, for archiving with the flight data
+    --imu-port PORT   Port the IMU sent from, for counting lost packets
+                      (default 35020)
+"#;
+
+
+fn seconds(time: time::Duration) -> f64 {
+    time.as_secs() as f64 + (time.subsec_nanos() as f64 / 1e9)
+}
+
+// A JSON number, or null if we don't have one
+fn json(value: Option<f64>) -> String {
+    match value {
+        Some(v) if v.is_finite() => v.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+// A line of the text report, or a placeholder if we don't have the value
+fn text(value: Option<f64>, unit: &str) -> String {
+    match value {
+        Some(v) => format!("{:.2} {}", v, unit),
+        None => "-".to_string(),
+    }
+}
+
+
+fn fail(message: &str) -> ! {
+    eprintln!("fc-report: {}", message);
+    eprint!("{}", USAGE);
+    process::exit(2);
+}
+
+
+fn main() {
+
+    let mut as_json = false;
+    let mut path: Option<String> = None;
+    let mut summary: report::Summary = Default::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => { as_json = true; },
+            "--imu-port" => {
+                let port = args.next().unwrap_or_else(|| fail("--imu-port needs a port"));
+                summary.imu_port = port.parse().unwrap_or_else(|_| fail(&format!("bad port {}", port)));
+            },
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with("--") => fail(&format!("unknown option {}", arg)),
+            _ if path.is_none() => { path = Some(arg); },
+            _ => fail("only one log file at a time"),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| fail("no log file given"));
+    let reader = match log::Reader::open(&path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("fc-report: {}: {}", path, e);
+            process::exit(1);
+        },
+    };
+
+    let mut truncated = false;
+    for record in reader {
+        match record {
+            Ok(record) => summary.add(&record),
+            Err(e) => {
+                eprintln!("fc-report: {}: {}", path, e);
+                truncated = true;
+            },
+        }
+    }
+
+    let max_altitude = summary.max_altitude.map(|p| p.value);
+    let above_launch = match (summary.max_altitude, summary.launch_altitude) {
+        (Some(apogee), Some(launch)) => Some(apogee.value - launch),
+        _ => None,
+    };
+    let apogee_time = summary.max_altitude.map(|p| seconds(p.time));
+    let max_velocity = summary.max_velocity.map(|p| p.value);
+    let max_acceleration = summary.max_acceleration.map(|p| p.value);
+    let burn_time = summary.burn_time().map(seconds);
+
+    if as_json {
+        println!("{{");
+        println!("  \"log\": \"{}\",", path.replace('\\', "\\\\").replace('"', "\\\""));
+        println!("  \"duration\": {},", json(Some(seconds(summary.duration))));
+        println!("  \"truncated\": {},", truncated);
+        println!("  \"max_altitude\": {},", json(max_altitude));
+        println!("  \"max_altitude_above_launch\": {},", json(above_launch));
+        println!("  \"apogee_time\": {},", json(apogee_time));
+        println!("  \"max_velocity\": {},", json(max_velocity));
+        println!("  \"max_velocity_time\": {},", json(summary.max_velocity.map(|p| seconds(p.time))));
+        println!("  \"max_acceleration\": {},", json(max_acceleration));
+        println!("  \"max_acceleration_time\": {},", json(summary.max_acceleration.map(|p| seconds(p.time))));
+        println!("  \"burn_time\": {},", json(burn_time));
+        println!("  \"adis_packets\": {},", summary.adis_packets);
+        println!("  \"adis_lost\": {},", summary.adis_lost);
+        println!("  \"adis_stale\": {},", summary.adis_stale);
+        println!("  \"adis_loss\": {},", json(Some(summary.adis_loss())));
+        println!("  \"telemetry_packets\": {},", summary.telemetry_packets);
+        println!("  \"restarts\": {}", summary.restarts);
+        println!("}}");
+    }
+    else {
+        println!("Flight summary: {}{}", path, if truncated { " (truncated)" } else { "" });
+        println!();
+        println!("  Log duration:        {}", text(Some(seconds(summary.duration)), "s"));
+        println!("  Max altitude:        {}", text(max_altitude, "m"));
+        println!("    above launch site: {}", text(above_launch, "m"));
+        println!("  Apogee at:           {}", text(apogee_time, "s"));
+        println!("  Max velocity:        {}", text(max_velocity, "m/s"));
+        println!("  Max acceleration:    {}", text(max_acceleration, "m/s/s"));
+        println!("  Burn time:           {}", text(burn_time, "s"));
+        println!();
+        println!("  ADIS packets:        {}", summary.adis_packets);
+        println!("    lost:              {} ({:.3}%)", summary.adis_lost, summary.adis_loss() * 100.0);
+        println!("    out of order:      {}", summary.adis_stale);
+        println!("  Telemetry packets:   {}", summary.telemetry_packets);
+        println!("  Restarts:            {}", summary.restarts);
+    }
+}
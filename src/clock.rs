@@ -0,0 +1,123 @@
+/*! # Clock
+
+Everything the flight computer logs or sends is stamped with the time since
+boot, and the state vector integrates over the time between IMU messages. The
+flight computer reads that time from a `Clock`.
+
+In flight it's a `SystemClock`, which counts up from when we booted using the
+monotonic system clock. For replaying a log, and in tests, a `ManualClock`
+only moves when it's told to. The same input then gives exactly the same log.
+*/
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time;
+
+
+/// Somewhere to get the time from.
+pub trait Clock {
+
+    /// Current time on the flight computer clock (time since boot).
+    fn now(&self) -> time::Duration;
+}
+
+
+/// The system's monotonic clock, counting from when we booted.
+pub struct SystemClock {
+
+    /// Instant we started
+    boot_time: time::Instant,
+
+    /// Time on the flight computer clock when we booted. This is zero unless
+    /// we resumed from a checkpoint.
+    offset: time::Duration,
+}
+
+
+impl SystemClock {
+
+    /// Start a clock at zero.
+    pub fn new() -> SystemClock {
+        SystemClock::starting_at(time::Duration::new(0, 0))
+    }
+
+    /// Start a clock that carries on from an earlier time, so timestamps
+    /// never go backwards after a restart.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **offset**: What the clock should read right now
+    ///
+    pub fn starting_at(offset: time::Duration) -> SystemClock {
+        SystemClock {
+            boot_time: time::Instant::now(),
+            offset,
+        }
+    }
+}
+
+
+impl Default for SystemClock {
+    fn default () -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+
+impl Clock for SystemClock {
+    fn now(&self) -> time::Duration {
+        time::Instant::now().duration_since(self.boot_time) + self.offset
+    }
+}
+
+
+/// A clock that only moves when we move it.
+///
+/// Clones share the same time, so keep one to move the clock after handing
+/// another to the flight computer.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::clock::{self, Clock};
+/// use std::time::Duration;
+///
+/// let clock = clock::ManualClock::new();
+/// let flight_computer_clock = clock.clone();
+///
+/// clock.set(Duration::from_millis(10));
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(flight_computer_clock.now(), Duration::from_millis(15));
+/// ```
+#[derive(Clone, Default)]
+pub struct ManualClock {
+
+    /// The time
+    time: Rc<Cell<time::Duration>>,
+}
+
+
+impl ManualClock {
+
+    /// A clock stopped at zero.
+    pub fn new() -> ManualClock {
+        Default::default()
+    }
+
+    /// Set the time.
+    pub fn set(&self, time: time::Duration) {
+        self.time.set(time);
+    }
+
+    /// Move the time forward.
+    pub fn advance(&self, step: time::Duration) {
+        self.time.set(self.time.get() + step);
+    }
+}
+
+
+impl Clock for ManualClock {
+    fn now(&self) -> time::Duration {
+        self.time.get()
+    }
+}
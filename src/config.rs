@@ -0,0 +1,568 @@
+/*! # Configuration
+
+Settings that we might want to change between flights without rebuilding the
+flight computer.
+
+The configuration file is plain text. Settings are `key = value` pairs grouped
+under `[section]` headings, and anything after a `#` is a comment:
+
+```text
+# Listen for sensors and for commands
+[network]
+listen = 0.0.0.0:36000
+listen = 0.0.0.0:36100
+
+# How long a partly built telemetry packet can wait before we send it
+[telemetry]
+max_age = 100
+
+# Send telemetry to two places
+destination = 192.168.1.20:35001
+destination = 239.255.0.1:35001 ttl=2 interface=wlan0
+
+# Settings for a single message type go in a section named after it
+[telemetry.ADIS]
+decimation = 8
+```
+
+Anything not in the file keeps its default value. Unknown sections or keys
+are an error, so a typo can't silently leave a setting at its default. A few
+settings (like `listen` and `destination`) can be given more than once to
+make a list.
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::net::SocketAddr;
+use std::time;
+use actuator;
+use control;
+use devices;
+use events;
+use io;
+use recovery;
+use shutdown;
+use state;
+use telemetry;
+
+
+/// Flight computer configuration.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::config;
+///
+/// let config = config::Config::parse("
+/// [telemetry]
+/// max_age = 20  # ms
+///
+/// [telemetry.ADIS]
+/// decimation = 8
+///
+/// [control]
+/// schedule = dynamic_pressure
+/// gains = 20000 2.0 0.1 0.0
+/// gains = 0     8.0 0.4 0.0
+/// ").unwrap();
+///
+/// assert_eq!(config.telemetry.max_age, std::time::Duration::from_millis(20));
+/// assert_eq!(config.telemetry.policy(*b"ADIS").decimation, 8);
+/// assert_eq!(config.control.schedule.points[0].0, 0.0);
+///
+/// // Limits the wrong way round (or not numbers) would crash the controller
+/// assert!(config::Config::parse("[control]\noutput_min = 20").is_err());
+/// assert!(config::Config::parse("[actuator]\ntravel_max = NaN").is_err());
+/// assert!(config::Config::parse("[control]\ngains = 0 inf 0.1 0.0").is_err());
+/// assert!(config::Config::parse("[control]\nroll = 2.0 NaN").is_err());
+/// ```
+pub struct Config {
+
+    /// Network settings
+    pub network: Network,
+
+    /// Control loop settings
+    pub control: Control,
+
+    /// Roll actuator settings
+    pub actuator: Actuator,
+
+    /// Telemetry settings
+    pub telemetry: Telemetry,
+
+    /// Crash recovery settings
+    pub recovery: Recovery,
+}
+
+
+/// Network settings (`[network]` section).
+pub struct Network {
+
+    /// Where to listen for messages (`listen`, one line per address). We
+    /// wait on all of them at once. If there are none, we listen on the
+    /// usual PSAS port on every interface.
+    pub listen: Vec<SocketAddr>,
+}
+
+
+/// Control loop settings (`[control]` section).
+pub struct Control {
+
+    /// How often to run the controller (`rate`, Hz)
+    pub rate: u32,
+
+    /// How old the IMU data can get before we stop actuating (`stale`,
+    /// milliseconds)
+    pub stale: time::Duration,
+
+    /// Time constant of the low pass filter on the derivative term
+    /// (`derivative_filter`, milliseconds). Zero turns the filter off.
+    pub derivative_filter: time::Duration,
+
+    /// Smallest output the actuator can do (`output_min`, degrees of fin
+    /// angle)
+    pub output_min: f64,
+
+    /// Largest output the actuator can do (`output_max`, degrees of fin
+    /// angle)
+    pub output_max: f64,
+
+    /// How to keep the integrator from winding up when the output is limited
+    /// (`anti_windup`, `conditional` or `back_calculation`)
+    pub anti_windup: control::AntiWindup,
+
+    /// Tracking gain for back calculation anti-windup (`tracking`, 1/s)
+    pub tracking: f64,
+
+    /// PID gains through the flight (`schedule`, `velocity` or
+    /// `dynamic_pressure`, and a `gains` line for each breakpoint)
+    pub schedule: control::Schedule,
+
+    /// Roll angles to hold from times after launch (`roll`, one line per
+    /// step with seconds after launch then degrees, or `rate` to go back to
+    /// holding the roll rate at zero)
+    pub roll_program: control::RollProgram,
+
+    /// When in the flight to move the fins (`after_burnout`, `min_velocity`
+    /// in m/s, and `before_apogee`)
+    pub window: control::Window,
+}
+
+
+impl Default for Control {
+    fn default () -> Control {
+        Control {
+            rate: 100,
+            stale: time::Duration::from_millis(50),
+            derivative_filter: time::Duration::from_millis(20),
+            output_min: -15.0,
+            output_max: 15.0,
+            anti_windup: control::AntiWindup::Conditional,
+            tracking: 1.0,
+            schedule: Default::default(),
+            roll_program: Default::default(),
+            window: Default::default(),
+        }
+    }
+}
+
+
+impl Control {
+
+    /// Time between controller steps.
+    pub fn period(&self) -> time::Duration {
+        time::Duration::from_nanos(1000000000 / self.rate as u64)
+    }
+}
+
+
+/// Roll actuator settings (`[actuator]` section).
+pub struct Actuator {
+
+    /// Where to send fin commands (`address`, an address and port with the
+    /// same options as a telemetry destination). If there isn't one, fin
+    /// commands are only logged.
+    pub destination: Option<telemetry::Destination>,
+
+    /// Fastest the fins can turn (`slew_rate`, deg/s). Zero means no limit.
+    pub slew_rate: f64,
+
+    /// Furthest the fins can turn one way (`travel_min`, degrees)
+    pub travel_min: f64,
+
+    /// Furthest the fins can turn the other way (`travel_max`, degrees)
+    pub travel_max: f64,
+
+    /// Fin angle to servo position (`calibration`, one line per point with
+    /// degrees then servo position)
+    pub calibration: actuator::Calibration,
+}
+
+
+impl Default for Actuator {
+    fn default () -> Actuator {
+        Actuator {
+            destination: None,
+            slew_rate: 300.0,
+            travel_min: -15.0,
+            travel_max: 15.0,
+            calibration: Default::default(),
+        }
+    }
+}
+
+
+/// Crash recovery settings (`[recovery]` section).
+pub struct Recovery {
+
+    /// Longest we can have been down for and still resume from a checkpoint
+    /// (`max_downtime`, milliseconds). After longer than this the flight has
+    /// moved on too far for the old state vector to be any use, or the
+    /// checkpoint is left over from a test, so we start fresh instead.
+    pub max_downtime: time::Duration,
+}
+
+
+impl Default for Recovery {
+    fn default () -> Recovery {
+        Recovery {
+            max_downtime: time::Duration::from_secs(10),
+        }
+    }
+}
+
+
+/// Telemetry settings (`[telemetry]` section).
+pub struct Telemetry {
+
+    /// The longest a message may wait in a partly built telemetry packet
+    /// before we send the packet anyway (`max_age`, milliseconds). Zero means
+    /// only send full packets.
+    pub max_age: time::Duration,
+
+    /// Bandwidth budget for the telemetry link (`bandwidth`, bytes/s). Zero
+    /// means unlimited.
+    pub bandwidth: u64,
+
+    /// How far we can burst over the bandwidth budget (`burst`, bytes).
+    pub burst: u64,
+
+    /// Where to send telemetry (`destination`, one line per destination).
+    /// If there are none, telemetry goes to a ground station on this machine.
+    pub destinations: Vec<telemetry::Destination>,
+
+    /// Policy for each message type (`[telemetry.NAME]` sections, with
+    /// `enabled`, `decimation` and `priority` settings).
+    pub policies: HashMap<[u8; 4], telemetry::Policy>,
+}
+
+
+impl Telemetry {
+
+    /// The telemetry policy for a message type.
+    ///
+    /// Message types without a policy of their own get the default policy.
+    pub fn policy(&self, name: [u8; 4]) -> telemetry::Policy {
+        match self.policies.get(&name) {
+            Some(policy) => *policy,
+            None => Default::default(),
+        }
+    }
+}
+
+
+impl Default for Config {
+    fn default () -> Config {
+
+        // Raw IMU data is the bulk of what we send, so it goes first when the
+        // link is over budget. The state vector is updated with every IMU
+        // message but the ground doesn't need it that often. Events are rare
+        // and important.
+        let mut policies = HashMap::new();
+        policies.insert(devices::ADIS_NAME, telemetry::Policy { priority: telemetry::Priority::Low, ..Default::default() });
+        policies.insert(state::STATE_NAME, telemetry::Policy { decimation: 8, priority: telemetry::Priority::High, ..Default::default() });
+        policies.insert(io::SEQE_NAME, Default::default());
+        policies.insert(io::SEQS_NAME, Default::default());
+        policies.insert(io::LINK_NAME, Default::default());
+        policies.insert(io::SEND_NAME, Default::default());
+        policies.insert(events::LOOP_NAME, Default::default());
+        policies.insert(control::CONTROL_NAME, telemetry::Policy { decimation: 10, priority: telemetry::Priority::Low, ..Default::default() });
+        policies.insert(actuator::ROLL_NAME, telemetry::Policy { decimation: 10, priority: telemetry::Priority::Low, ..Default::default() });
+        policies.insert(recovery::RESTART_NAME, telemetry::Policy { priority: telemetry::Priority::Critical, ..Default::default() });
+        policies.insert(shutdown::SHUTDOWN_NAME, telemetry::Policy { priority: telemetry::Priority::Critical, ..Default::default() });
+
+        Config {
+            network: Network {
+                listen: Vec::new(),
+            },
+            control: Default::default(),
+            actuator: Default::default(),
+            telemetry: Telemetry {
+                max_age: time::Duration::from_millis(100),
+                bandwidth: 0,
+                burst: 8192,
+                destinations: Vec::new(),
+                policies,
+            },
+            recovery: Default::default(),
+        }
+    }
+}
+
+
+// Read a whole number
+fn parse_number<T: ::std::str::FromStr>(value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("expected a number, got '{}'", value)),
+    }
+}
+
+
+// Read true or false
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected true or false, got '{}'", value)),
+    }
+}
+
+
+// Read a four character message name
+fn parse_name(value: &str) -> Result<[u8; 4], String> {
+    let bytes = value.as_bytes();
+    if bytes.len() != 4 || !value.is_ascii() {
+        return Err(format!("expected a four character message name, got '{}'", value));
+    }
+    Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+
+// Read a number of milliseconds
+fn parse_millis(value: &str) -> Result<time::Duration, String> {
+    match value.parse::<u64>() {
+        Ok(ms) => Ok(time::Duration::from_millis(ms)),
+        Err(_) => Err(format!("expected milliseconds, got '{}'", value)),
+    }
+}
+
+
+// Check a pair of limits are finite and the right way round
+fn check_range(name: &str, min: f64, max: f64) -> Result<(), String> {
+    if !min.is_finite() || !max.is_finite() {
+        return Err(format!("{} limits must be numbers, got {} and {}", name, min, max));
+    }
+    if min > max {
+        return Err(format!("{} minimum ({}) is more than the maximum ({})", name, min, max));
+    }
+    Ok(())
+}
+
+
+// Check a setting is a finite number and not negative
+fn check_positive(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("{} must be a number of at least 0, got {}", name, value));
+    }
+    Ok(())
+}
+
+
+impl Config {
+
+    /// Read configuration from a file.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **path**: The configuration file
+    ///
+    pub fn load(path: &str) -> Result<Config, Error> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Config::parse(&text)
+    }
+
+    /// Read configuration from a string.
+    ///
+    /// ## Returns:
+    ///
+    /// A Result with the configuration, or an error of kind `InvalidData`
+    /// that says which line was wrong.
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let mut config: Config = Default::default();
+        let mut section = String::new();
+
+        for (number, line) in text.lines().enumerate() {
+
+            // Strip comments and whitespace
+            let line = match line.find('#') {
+                Some(start) => &line[..start],
+                None => line,
+            }.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let result = if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                config.check_section(&section)
+            }
+            else {
+                match line.find('=') {
+                    Some(equals) => config.set(&section, line[..equals].trim(), line[equals + 1..].trim()),
+                    None => Err(format!("expected 'key = value', got '{}'", line)),
+                }
+            };
+
+            if let Err(message) = result {
+                return Err(Error::new(ErrorKind::InvalidData, format!("line {}: {}", number + 1, message)));
+            }
+        }
+
+        if let Err(message) = config.check() {
+            return Err(Error::new(ErrorKind::InvalidData, message));
+        }
+
+        Ok(config)
+    }
+
+    // Make sure settings that depend on each other make sense together, and
+    // that nothing the controller clamps with is NaN or infinite
+    fn check(&self) -> Result<(), String> {
+        check_range("control output", self.control.output_min, self.control.output_max)?;
+        check_range("actuator travel", self.actuator.travel_min, self.actuator.travel_max)?;
+        check_positive("control tracking", self.control.tracking)?;
+        check_positive("control min_velocity", self.control.window.min_velocity)?;
+        check_positive("actuator slew_rate", self.actuator.slew_rate)?;
+        for &(breakpoint, ref gains) in &self.control.schedule.points {
+            if !breakpoint.is_finite() || !gains.kp.is_finite() || !gains.ki.is_finite() || !gains.kd.is_finite() {
+                return Err(format!("control gains {} {} {} {} are not numbers", breakpoint, gains.kp, gains.ki, gains.kd));
+            }
+        }
+        for &(time, angle) in &self.control.roll_program.steps {
+            if !time.is_finite() || !angle.is_none_or(f64::is_finite) {
+                return Err(format!("control roll step at {} s is not a number", time));
+            }
+        }
+        for &(angle, position) in &self.actuator.calibration.points {
+            if !angle.is_finite() || !position.is_finite() {
+                return Err(format!("actuator calibration point {} {} is not a number", angle, position));
+            }
+            if position < 0.0 || position > u16::MAX as f64 {
+                return Err(format!("actuator calibration position {} is out of range", position));
+            }
+        }
+        Ok(())
+    }
+
+    // Make sure we know about a section
+    fn check_section(&mut self, section: &str) -> Result<(), String> {
+        if let Some(name) = section.strip_prefix("telemetry.") {
+            let name = parse_name(name)?;
+            let policy = self.telemetry.policy(name);
+            self.telemetry.policies.insert(name, policy);
+            return Ok(());
+        }
+        match section {
+            "network" | "control" | "actuator" | "telemetry" | "recovery" => Ok(()),
+            _ => Err(format!("unknown section [{}]", section)),
+        }
+    }
+
+    // Apply a single setting
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        if let Some(name) = section.strip_prefix("telemetry.") {
+            let policy = self.telemetry.policies.get_mut(&parse_name(name)?).unwrap();
+            match key {
+                "enabled" => { policy.enabled = parse_bool(value)?; },
+                "decimation" => {
+                    policy.decimation = parse_number(value)?;
+                    if policy.decimation == 0 {
+                        return Err("decimation must be at least 1".to_string());
+                    }
+                },
+                "priority" => {
+                    policy.priority = match telemetry::Priority::from_name(value) {
+                        Some(priority) => priority,
+                        None => { return Err(format!("expected low, normal, high or critical, got '{}'", value)); },
+                    };
+                },
+                _ => { return Err(format!("unknown setting '{}' in [{}]", key, section)); },
+            }
+            return Ok(());
+        }
+        match (section, key) {
+            ("network", "listen") => {
+                match value.parse() {
+                    Ok(addr) => { self.network.listen.push(addr); },
+                    Err(_) => { return Err(format!("expected an address and port, got '{}'", value)); },
+                }
+            },
+            ("control", "rate") => {
+                self.control.rate = parse_number(value)?;
+                if self.control.rate == 0 {
+                    return Err("rate must be at least 1".to_string());
+                }
+            },
+            ("control", "stale") => { self.control.stale = parse_millis(value)?; },
+            ("control", "derivative_filter") => { self.control.derivative_filter = parse_millis(value)?; },
+            ("control", "output_min") => { self.control.output_min = parse_number(value)?; },
+            ("control", "output_max") => { self.control.output_max = parse_number(value)?; },
+            ("control", "anti_windup") => {
+                self.control.anti_windup = match control::AntiWindup::from_name(value) {
+                    Some(anti_windup) => anti_windup,
+                    None => { return Err(format!("expected conditional or back_calculation, got '{}'", value)); },
+                };
+            },
+            ("control", "tracking") => { self.control.tracking = parse_number(value)?; },
+            ("control", "schedule") => {
+                self.control.schedule.by = match control::ScheduleBy::from_name(value) {
+                    Some(by) => by,
+                    None => { return Err(format!("expected velocity or dynamic_pressure, got '{}'", value)); },
+                };
+            },
+            ("control", "gains") => {
+                let numbers = value.split_whitespace().map(parse_number).collect::<Result<Vec<f64>, String>>()?;
+                if numbers.len() != 4 {
+                    return Err(format!("expected a breakpoint then kp, ki and kd, got '{}'", value));
+                }
+                self.control.schedule.add(numbers[0], control::Gains { kp: numbers[1], ki: numbers[2], kd: numbers[3] });
+            },
+            ("control", "roll") => {
+                let words: Vec<&str> = value.split_whitespace().collect();
+                if words.len() != 2 {
+                    return Err(format!("expected a time after launch then an angle or 'rate', got '{}'", value));
+                }
+                let angle = match words[1] {
+                    "rate" => None,
+                    angle => Some(parse_number(angle)?),
+                };
+                self.control.roll_program.add(parse_number(words[0])?, angle);
+            },
+            ("control", "after_burnout") => { self.control.window.after_burnout = parse_bool(value)?; },
+            ("control", "min_velocity") => { self.control.window.min_velocity = parse_number(value)?; },
+            ("control", "before_apogee") => { self.control.window.before_apogee = parse_bool(value)?; },
+            ("actuator", "address") => { self.actuator.destination = Some(telemetry::Destination::parse(value)?); },
+            ("actuator", "slew_rate") => { self.actuator.slew_rate = parse_number(value)?; },
+            ("actuator", "travel_min") => { self.actuator.travel_min = parse_number(value)?; },
+            ("actuator", "travel_max") => { self.actuator.travel_max = parse_number(value)?; },
+            ("actuator", "calibration") => {
+                let numbers = value.split_whitespace().map(parse_number).collect::<Result<Vec<f64>, String>>()?;
+                if numbers.len() != 2 {
+                    return Err(format!("expected a fin angle then a servo position, got '{}'", value));
+                }
+                self.actuator.calibration.add(numbers[0], numbers[1]);
+            },
+            ("telemetry", "max_age") => { self.telemetry.max_age = parse_millis(value)?; },
+            ("telemetry", "bandwidth") => { self.telemetry.bandwidth = parse_number(value)?; },
+            ("telemetry", "burst") => { self.telemetry.burst = parse_number(value)?; },
+            ("telemetry", "destination") => { self.telemetry.destinations.push(telemetry::Destination::parse(value)?); },
+            ("recovery", "max_downtime") => { self.recovery.max_downtime = parse_millis(value)?; },
+            _ => { return Err(format!("unknown setting '{}' in [{}]", key, section)); },
+        }
+        Ok(())
+    }
+}
@@ -6,9 +6,48 @@ For [PSAS Launch-12](https://github.com/psas/Launch-12) there was only one
 controllable device: a roll angle actuator (small twist-able set of fins). The
 fin angle is computed using the output of a PID loop that is then normalized to
 velocity.
+
+The controller runs on a fixed rate timer rather than every time IMU data
+arrives, so the time step (and what the gains mean) doesn't change with
+network jitter or lost packets. Each step uses the latest state vector. If
+that state is too old to trust we stop actuating until fresh data arrives.
+
+The PID loop scales everything by the real time step. The derivative is low
+pass filtered, since differentiating a noisy gyro just gives you more noise.
+The fins can only turn so far, so the output is limited, and the integrator is
+kept from winding up while the output is pinned at a limit. Every step the
+terms of the PID loop are logged in a `CTRL` message so we can tune it from
+flight data.
+
+How much the fins can do changes by orders of magnitude between leaving the
+rail and max-Q, so one set of gains won't work for the whole flight. Gains
+can be scheduled on velocity or dynamic pressure: a table of gains at
+breakpoints, interpolated in between. The gains in use go in the `CTRL`
+message too.
+
+Normally we hold the roll rate at zero. For experiments that need to point
+somewhere, a roll program can instead hold the rocket at particular roll
+angles from particular times after launch.
+
+We only want to move the fins for part of the flight: not on the rail, not
+while the motor is pushing us around, and not fighting the parachute on the
+way down. Outside the control window the fins go to neutral, and when the
+window opens the controller starts from scratch.
 */
 
+extern crate byteorder;
+
+use std::io::Cursor;
+use std::mem;
+use config;
 use state;
+use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+
+/// Control debug message size (bytes)
+pub const SIZE_OF_CONTROL: usize = 92;
+
+/// Control debug message name (ASCII: CTRL)
+pub const CONTROL_NAME: [u8;4] = [67, 84, 82, 76];
 
 /// K_p, the proportional constant for PID loop
 const KP: f64 = 5.0;
@@ -19,79 +58,699 @@ const KI: f64 = 0.01;
 /// K_d, the derivative constant for PID loop
 const KD: f64 = 0.0;
 
-/// The target control value (0 deg/s roll rate, in this case)
+/// The target control value when we're not following a roll program (0 deg/s
+/// roll rate)
 const PID_TARGET: f64 = 0.0;
 
-/// Maximum integrator value we tolerate
-const MAX_INTEGRATOR: f64 =  10000.0;
+/// Slowest we can be going for the fins to do anything useful [m/s]
+const MIN_VELOCITY: f64 = 50.0;
+
+/// Air density at sea level [kg/m³]
+const SEA_LEVEL_DENSITY: f64 = 1.225;
+
+/// Scale height of the atmosphere [m]
+const SCALE_HEIGHT: f64 = 8500.0;
+
+
+/// PID loop gains.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Gains {
+
+    /// K_p, the proportional constant
+    pub kp: f64,
+
+    /// K_i, the integral constant
+    pub ki: f64,
+
+    /// K_d, the derivative constant
+    pub kd: f64,
+}
+
+
+impl Default for Gains {
+    fn default () -> Gains {
+        Gains { kp: KP, ki: KI, kd: KD }
+    }
+}
+
+
+/// What gains are scheduled on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScheduleBy {
+
+    /// Speed [m/s]
+    Velocity,
+
+    /// Dynamic pressure [Pa]
+    DynamicPressure,
+}
+
+
+impl ScheduleBy {
+
+    /// Read what to schedule on from its name (`velocity` or
+    /// `dynamic_pressure`).
+    pub fn from_name(name: &str) -> Option<ScheduleBy> {
+        match name {
+            "velocity" => Some(ScheduleBy::Velocity),
+            "dynamic_pressure" => Some(ScheduleBy::DynamicPressure),
+            _ => None,
+        }
+    }
+}
+
+
+/// A gain schedule.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{control, state};
+///
+/// let mut schedule = control::Schedule::new(control::ScheduleBy::Velocity);
+/// schedule.add(100.0, control::Gains { kp: 4.0, ki: 0.2, kd: 0.0 });
+/// schedule.add(0.0, control::Gains { kp: 8.0, ki: 0.4, kd: 0.0 });
+///
+/// let state = state::State { vel_up: 25.0, ..Default::default() };
+/// assert_eq!(schedule.gains(&state).kp, 7.0);
+///
+/// // Past the end of the table we stick with the last gains
+/// let state = state::State { vel_up: 300.0, ..Default::default() };
+/// assert_eq!(schedule.gains(&state).kp, 4.0);
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct Schedule {
+
+    /// What the breakpoints are in
+    pub by: ScheduleBy,
+
+    /// Gains at each breakpoint, in order of breakpoint. With no breakpoints
+    /// we always use the default gains.
+    pub points: Vec<(f64, Gains)>,
+}
+
+
+impl Default for Schedule {
+    fn default () -> Schedule {
+        Schedule::new(ScheduleBy::Velocity)
+    }
+}
+
+
+impl Schedule {
+
+    /// An empty schedule.
+    pub fn new(by: ScheduleBy) -> Schedule {
+        Schedule { by, points: Vec::new() }
+    }
+
+    /// Add gains at a breakpoint.
+    pub fn add(&mut self, breakpoint: f64, gains: Gains) {
+        let index = self.points.iter().position(|&(at, _)| at > breakpoint).unwrap_or(self.points.len());
+        self.points.insert(index, (breakpoint, gains));
+    }
+
+    /// The value gains are scheduled on for a state.
+    pub fn value(&self, state: &state::State) -> f64 {
+        match self.by {
+            ScheduleBy::Velocity => state.vel_up.abs(),
+            ScheduleBy::DynamicPressure => {
+
+                // Exponential atmosphere, good enough to schedule on
+                let density = SEA_LEVEL_DENSITY * (-state.altitude / SCALE_HEIGHT).exp();
+                0.5 * density * state.vel_up * state.vel_up
+            },
+        }
+    }
+
+    /// Gains for a state, interpolated between the breakpoints either side.
+    pub fn gains(&self, state: &state::State) -> Gains {
+        let value = self.value(state);
+
+        let after = match self.points.iter().position(|&(at, _)| at > value) {
+            Some(0) => { return self.points[0].1; },
+            Some(after) => after,
+            None => { return self.points.last().map(|&(_, gains)| gains).unwrap_or_default(); },
+        };
+
+        let (x0, g0) = self.points[after - 1];
+        let (x1, g1) = self.points[after];
+        let f = (value - x0) / (x1 - x0);
+        Gains {
+            kp: g0.kp + f * (g1.kp - g0.kp),
+            ki: g0.ki + f * (g1.ki - g0.ki),
+            kd: g0.kd + f * (g1.kd - g0.kd),
+        }
+    }
+}
+
+
+/// How to keep the integrator from winding up when the output is saturated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AntiWindup {
+
+    /// Stop integrating while the output is saturated and the error would
+    /// push it further into saturation.
+    Conditional,
+
+    /// Bleed the integrator off by how far the output is past the limit,
+    /// times a tracking gain.
+    BackCalculation,
+}
+
+
+impl AntiWindup {
+
+    /// Read an anti-windup method from its name (`conditional` or
+    /// `back_calculation`).
+    pub fn from_name(name: &str) -> Option<AntiWindup> {
+        match name {
+            "conditional" => Some(AntiWindup::Conditional),
+            "back_calculation" => Some(AntiWindup::BackCalculation),
+            _ => None,
+        }
+    }
+}
+
+
+/// What the controller is trying to do.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+
+    /// Hold this roll rate [deg/s]
+    RollRate(f64),
+
+    /// Hold this roll angle [deg]
+    RollAngle(f64),
+}
+
+
+impl Target {
+
+    /// How far off target we are in a state.
+    pub fn error(&self, state: &state::State) -> f64 {
+        match *self {
+            Target::RollRate(rate) => rate - state.roll_rate,
+
+            // The short way round
+            Target::RollAngle(angle) => (angle - state.roll_angle + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
+}
+
+
+/// A roll program: roll angles to hold from times after launch.
+///
+/// Until the first step, and on the pad, we hold the roll rate at zero.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{control, state};
+///
+/// let mut program: control::RollProgram = Default::default();
+/// program.add(5.0, Some(90.0));
+/// program.add(8.0, None);
+///
+/// let mut state = state::State { time: 6000000000, launch_time: Some(0), ..Default::default() };
+/// assert_eq!(program.target(&state), control::Target::RollAngle(90.0));
+///
+/// // Then back to holding the roll rate
+/// state.time = 9000000000;
+/// assert_eq!(program.target(&state), control::Target::RollRate(0.0));
+/// ```
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct RollProgram {
+
+    /// Each step: time after launch [s] and the roll angle to hold from then
+    /// [deg], or None to go back to holding the roll rate at zero. In order
+    /// of time.
+    pub steps: Vec<(f64, Option<f64>)>,
+}
+
+
+impl RollProgram {
+
+    /// Add a step to the program.
+    pub fn add(&mut self, time: f64, angle: Option<f64>) {
+        let index = self.steps.iter().position(|&(at, _)| at > time).unwrap_or(self.steps.len());
+        self.steps.insert(index, (time, angle));
+    }
+
+    /// What to aim for in a state.
+    pub fn target(&self, state: &state::State) -> Target {
+        let since_launch = match state.since_launch() {
+            Some(since_launch) => since_launch,
+            None => { return Target::RollRate(PID_TARGET); },
+        };
+        match self.steps.iter().rev().find(|&&(at, _)| at <= since_launch) {
+            Some(&(_, Some(angle))) => Target::RollAngle(angle),
+            _ => Target::RollRate(PID_TARGET),
+        }
+    }
+}
+
+
+/// When in the flight the controller is allowed to move the fins.
+///
+/// The window is never open on the pad.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{control, state};
+///
+/// let window: control::Window = Default::default();
+///
+/// let state = state::State { phase: state::Phase::Boost, vel_up: 150.0, ..Default::default() };
+/// assert!(!window.open(&state));
+///
+/// let state = state::State { phase: state::Phase::Coast, vel_up: 150.0, ..Default::default() };
+/// assert!(window.open(&state));
+///
+/// // Too slow for the fins to bite
+/// let state = state::State { phase: state::Phase::Coast, vel_up: 20.0, ..Default::default() };
+/// assert!(!window.open(&state));
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Window {
+
+    /// Wait for burnout, rather than starting at launch
+    pub after_burnout: bool,
+
+    /// Slowest we can be going [m/s]
+    pub min_velocity: f64,
+
+    /// Stop at apogee
+    pub before_apogee: bool,
+}
+
+
+impl Default for Window {
+    fn default () -> Window {
+        Window {
+            after_burnout: true,
+            min_velocity: MIN_VELOCITY,
+            before_apogee: true,
+        }
+    }
+}
+
+
+impl Window {
+
+    /// Is the controller allowed to move the fins in this state?
+    pub fn open(&self, state: &state::State) -> bool {
+        let start = if self.after_burnout { state::Phase::Coast } else { state::Phase::Boost };
+        state.phase >= start
+            && state.vel_up.abs() >= self.min_velocity
+            && !(self.before_apogee && state.phase == state::Phase::Descent)
+    }
+}
+
+
+/// What the controller did in one step (`CTRL` message).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Terms {
+
+    /// Time since the last step [s]
+    pub dt: f64,
+
+    /// Gains in use
+    pub gains: Gains,
+
+    /// What we were aiming for
+    pub target: Target,
+
+    /// Target minus measured value
+    pub error: f64,
+
+    /// Proportional term
+    pub proportional: f64,
+
+    /// Integral term
+    pub integral: f64,
+
+    /// Derivative term (after filtering)
+    pub derivative: f64,
+
+    /// Sum of the terms, before limiting
+    pub requested: f64,
+
+    /// What we asked the actuator for
+    pub output: f64,
+
+    /// False if we're not actuating at all
+    pub active: bool,
+
+    /// False if we're outside the control window
+    pub enabled: bool,
+
+    /// True if the output was limited
+    pub saturated: bool,
+}
+
+
+impl Terms {
+
+    /// Read control terms back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> Terms {
+        let mut message = Cursor::new(message_buffer);
+
+        Terms {
+            dt:           message.read_f64::<BigEndian>().unwrap(),
+            gains: Gains {
+                kp:       message.read_f64::<BigEndian>().unwrap(),
+                ki:       message.read_f64::<BigEndian>().unwrap(),
+                kd:       message.read_f64::<BigEndian>().unwrap(),
+            },
+            target: match (message.read_u8().unwrap(), message.read_f64::<BigEndian>().unwrap()) {
+                (1, angle) => Target::RollAngle(angle),
+                (_, rate) => Target::RollRate(rate),
+            },
+            error:        message.read_f64::<BigEndian>().unwrap(),
+            proportional: message.read_f64::<BigEndian>().unwrap(),
+            integral:     message.read_f64::<BigEndian>().unwrap(),
+            derivative:   message.read_f64::<BigEndian>().unwrap(),
+            requested:    message.read_f64::<BigEndian>().unwrap(),
+            output:       message.read_f64::<BigEndian>().unwrap(),
+            active:       message.read_u8().unwrap() != 0,
+            enabled:      message.read_u8().unwrap() != 0,
+            saturated:    message.read_u8().unwrap() != 0,
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_CONTROL] {
+        let mut buffer = [0u8; SIZE_OF_CONTROL];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_f64::<BigEndian>(self.dt).unwrap();
+            message.write_f64::<BigEndian>(self.gains.kp).unwrap();
+            message.write_f64::<BigEndian>(self.gains.ki).unwrap();
+            message.write_f64::<BigEndian>(self.gains.kd).unwrap();
+            match self.target {
+                Target::RollRate(rate) => {
+                    message.write_u8(0).unwrap();
+                    message.write_f64::<BigEndian>(rate).unwrap();
+                },
+                Target::RollAngle(angle) => {
+                    message.write_u8(1).unwrap();
+                    message.write_f64::<BigEndian>(angle).unwrap();
+                },
+            }
+            message.write_f64::<BigEndian>(self.error).unwrap();
+            message.write_f64::<BigEndian>(self.proportional).unwrap();
+            message.write_f64::<BigEndian>(self.integral).unwrap();
+            message.write_f64::<BigEndian>(self.derivative).unwrap();
+            message.write_f64::<BigEndian>(self.requested).unwrap();
+            message.write_f64::<BigEndian>(self.output).unwrap();
+            message.write_u8(self.active as u8).unwrap();
+            message.write_u8(self.enabled as u8).unwrap();
+            message.write_u8(self.saturated as u8).unwrap();
+        }
+        buffer
+    }
+}
 
-/// Minimum integrator value we tolerate
-const MIN_INTEGRATOR: f64 = -10000.0;
 
 /// Controller
 pub struct Control {
 
-    /// Current PID Integral
+    /// Gains to use through the flight
+    pub schedule: Schedule,
+
+    /// Roll angles to hold through the flight
+    pub roll_program: RollProgram,
+
+    /// Time constant of the derivative filter [s]. Zero turns the filter
+    /// off.
+    pub derivative_filter: f64,
+
+    /// Smallest output the actuator can do
+    pub output_min: f64,
+
+    /// Largest output the actuator can do
+    pub output_max: f64,
+
+    /// How to keep the integrator from winding up
+    pub anti_windup: AntiWindup,
+
+    /// Tracking gain for back calculation anti-windup [1/s]
+    pub tracking: f64,
+
+    /// When in the flight we're allowed to move the fins
+    pub window: Window,
+
+    /// Current PID Integral term
     pub integral: f64,
 
     /// Last error for PID Derivative
     pub last_error: f64,
+
+    /// Filtered derivative of the error
+    pub derivative: f64,
+
+    /// What we were aiming for last step
+    pub target: Option<Target>,
+
+    /// Output of the last step
+    pub correction: f64,
+
+    /// False if we've stopped actuating because the state is stale
+    pub active: bool,
+
+    /// False if we're outside the control window
+    pub enabled: bool,
 }
 
-/// Initialize PID loop to zeros.
+/// Initialize PID loop to zeros, with the default settings.
 impl Default for Control {
     fn default () -> Control {
+        Control::new(&Default::default())
+    }
+}
+
+impl Control {
+
+    /// A controller with settings from the configuration.
+    pub fn new(config: &config::Control) -> Control {
         Control {
+            schedule: config.schedule.clone(),
+            roll_program: config.roll_program.clone(),
+            derivative_filter: config.derivative_filter.as_secs() as f64 + config.derivative_filter.subsec_nanos() as f64 / 1e9,
+            output_min: config.output_min,
+            output_max: config.output_max,
+            anti_windup: config.anti_windup,
+            tracking: config.tracking,
+            window: config.window,
             integral: 0.0,
             last_error: 0.0,
+            derivative: 0.0,
+            target: None,
+            correction: 0.0,
+            active: false,
+            enabled: false,
         }
     }
-}
-
-impl Control {
 
     /// A PID loop controller.
     ///
-    /// This is expected to be called every time there is a new State Vector
+    /// This is expected to be called at a fixed rate with the latest State
+    /// Vector, while the control window is open. The first step after the
+    /// window opens starts the PID loop over from zero.
     ///
     /// ## Parameters:
     ///
     /// - **state**: State vector to operate on
+    /// - **dt**: Time since the last step [s]
+    ///
+    /// ## Returns:
+    ///
+    /// Each term of the PID loop and the output.
+    ///
+    /// # Example
     ///
-    pub fn pid(&mut self, state: &state::State) {
+    /// ```
+    /// use rust_fc::{control, state};
+    ///
+    /// let mut controller: control::Control = Default::default();
+    /// let state = state::State { roll_rate: 2.0, ..Default::default() };
+    ///
+    /// // The integral grows with time, not with how often we're called
+    /// controller.pid(&state, 0.01);
+    /// let terms = controller.pid(&state, 0.01);
+    /// assert!((terms.integral + 0.0002).abs() < 1e-12);
+    /// assert!((terms.output + 10.0002).abs() < 1e-12);
+    /// assert!(!terms.saturated);
+    ///
+    /// // Spinning fast enough pins the fins, and the integrator stops
+    /// let state = state::State { roll_rate: 50.0, ..Default::default() };
+    /// let terms = controller.pid(&state, 0.01);
+    /// assert!(terms.saturated);
+    /// assert_eq!(terms.output, controller.output_min);
+    /// assert_eq!(controller.pid(&state, 0.01).integral, terms.integral);
+    ///
+    /// // Leaving the control window and coming back starts over
+    /// controller.disable(&state, 0.01);
+    /// assert_eq!(controller.pid(&state, 0.01).integral, 0.0);
+    /// ```
+    pub fn pid(&mut self, state: &state::State, dt: f64) -> Terms {
+        self.pid_within(state, dt, f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    /// A PID loop controller, limited to what the actuator can reach this
+    /// step as well as the output limits.
+    ///
+    /// Limiting here rather than after the fact means the anti-windup knows
+    /// when the fins couldn't do what we asked.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **state**: State vector to operate on
+    /// - **dt**: Time since the last step [s]
+    /// - **min**: Smallest output the actuator can reach
+    /// - **max**: Largest output the actuator can reach
+    ///
+    /// ## Returns:
+    ///
+    /// Each term of the PID loop and the output.
+    pub fn pid_within(&mut self, state: &state::State, dt: f64, min: f64, max: f64) -> Terms {
+
+        // Don't carry anything over from the last time we were enabled
+        if !self.enabled {
+            self.integral = 0.0;
+            self.last_error = 0.0;
+            self.derivative = 0.0;
+            self.target = None;
+            self.enabled = true;
+        }
+
+        // Gains for where we are in the flight
+        let gains = self.schedule.gains(state);
 
         // Determine the error by taking the difference of the target and the
         // current value
-        let error = PID_TARGET - state.roll_rate;
+        let target = self.roll_program.target(state);
+        let error = target.error(state);
 
-        // Proportional stage
-        let proportional = KP * error;
+        // The error means something different when we switch between holding
+        // rate and angle, so don't take the derivative across a switch. The
+        // integral term is in output units, so it carries straight over.
+        let switched = self.target.map(|last| mem::discriminant(&last) != mem::discriminant(&target)).unwrap_or(true);
+        if switched {
+            self.last_error = error;
+            self.derivative = 0.0;
+        }
+        self.target = Some(target);
 
-        // Integral stage
-        let integral = KI * self.integral;
+        // Proportional stage
+        let proportional = gains.kp * error;
 
-        // Derivative stage
-        let derivative = KD * (error - self.last_error);
+        // Derivative stage, through a first order low pass filter
+        if dt > 0.0 {
+            let raw = (error - self.last_error) / dt;
+            let alpha = dt / (self.derivative_filter + dt);
+            self.derivative += alpha * (raw - self.derivative);
+        }
+        let derivative = gains.kd * self.derivative;
 
-        // Output of the PID controller
-        let correction = proportional + integral + derivative;
+        // Output of the PID controller, limited to what the actuator can do
+        let requested = proportional + self.integral + derivative;
+        let output = requested.clamp(self.output_min, self.output_max).clamp(min, max);
+        let saturated = output != requested;
 
+        // Integral stage, for next time. Don't let it wind up while we're
+        // saturated.
+        let integral = self.integral;
+        match self.anti_windup {
+            AntiWindup::Conditional => {
+                let winding_up = (requested > output && error > 0.0) || (requested < output && error < 0.0);
+                if !winding_up {
+                    self.integral += gains.ki * error * dt;
+                }
+            },
+            AntiWindup::BackCalculation => {
+                self.integral += (gains.ki * error + self.tracking * (output - requested)) * dt;
+            },
+        }
 
         // Store values for next loop:
         self.last_error = error;
 
-        // Add the error to the integral stage
-	    self.integral += error;
+        // Look normalized fin angle based on requested angular acceleration
+        //double output = estimate_alpha(correction, *state);
 
-        // Integrator clamping, helps dampen a run-away system
-        if self.integral > MAX_INTEGRATOR {
-            self.integral = MAX_INTEGRATOR;
+        self.correction = output;
+        self.active = true;
+
+        Terms {
+            dt,
+            gains,
+            target,
+            error,
+            proportional,
+            integral,
+            derivative,
+            requested,
+            output,
+            active: true,
+            enabled: true,
+            saturated,
         }
-        else if self.integral < MIN_INTEGRATOR {
-            self.integral = MIN_INTEGRATOR;
+    }
+
+    /// Stop actuating. The integrator is left alone so we can carry on when
+    /// we have good data again.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **state**: State vector, for the gains we would be using
+    /// - **dt**: Time since the last step [s]
+    ///
+    /// ## Returns:
+    ///
+    /// Terms for the log, with no output.
+    pub fn hold(&mut self, state: &state::State, dt: f64) -> Terms {
+        self.correction = 0.0;
+        self.active = false;
+
+        Terms {
+            dt,
+            gains: self.schedule.gains(state),
+            target: self.roll_program.target(state),
+            error: 0.0,
+            proportional: 0.0,
+            integral: self.integral,
+            derivative: 0.0,
+            requested: 0.0,
+            output: 0.0,
+            active: false,
+            enabled: self.enabled,
+            saturated: false,
         }
+    }
 
-	    // Look normalized fin angle based on requested angular acceleration
-	    //double output = estimate_alpha(correction, *state);
+    /// Put the fins to neutral because we're outside the control window. The
+    /// integrator is reset when the window opens again.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **state**: State vector, for the gains we would be using
+    /// - **dt**: Time since the last step [s]
+    ///
+    /// ## Returns:
+    ///
+    /// Terms for the log, with no output.
+    pub fn disable(&mut self, state: &state::State, dt: f64) -> Terms {
+        self.enabled = false;
+        self.hold(state, dt)
     }
 }
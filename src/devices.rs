@@ -10,6 +10,7 @@ use std::io::Cursor;
 use self::byteorder::{ReadBytesExt, BigEndian};
 
 /// Unwrapped and converted ADIS IMU data.
+#[derive(Clone)]
 pub struct ADIS {
 
     /// VCC [Volts]. The voltage coming into the IMU
@@ -72,7 +73,7 @@ pub const ADIS_NAME: [u8;4] = [65, 68, 73, 83];
 /// ## Parameters
 ///
 /// - **message_buffer**: A buffer of bytes at least as long as the ADIS
-/// message (`SIZE_OF_ADIS`).
+///   message (`SIZE_OF_ADIS`).
 pub fn recv_adis(message_buffer: &[u8]) -> ADIS {
 
     let mut message = Cursor::new(message_buffer);
@@ -0,0 +1,596 @@
+/*! # Event Loop
+
+The original flight computer generated its `main` from a description of which
+code handles which messages ([elderberry](https://github.com/psas/elderberry)).
+Here that wiring is done at run time instead: anything that wants to see
+incoming messages registers a `Handler` for the message names or ports it
+understands, and anything that needs to run regularly registers a `Timer`.
+The `EventLoop` then waits for packets, checks their sequence numbers, passes
+each message to its handlers and fires timers when they're due.
+
+Handlers and timers keep their own state, and share a `flight::Flight` for
+what more than one of them needs: the state vector, and how packets are
+getting through. Several handlers can take the same message (one to log it
+and one to update the state vector, say), and they get it in the order they
+were registered.
+
+Adding a device means writing a handler for its messages and registering it,
+without touching the loop itself.
+
+The loop waits on every socket we listen on at once (see
+`transport::UdpListener`) and on the next timer, so nothing has to wait for
+IMU data to arrive before it gets a turn. To check the loop keeps up, we time
+how long each message waits between the loop waking up and its handler being
+called, and how late each timer fires. Those go out every second in a `LOOP`
+message.
+*/
+
+extern crate byteorder;
+
+use std::io::Cursor;
+use std::time;
+use flight;
+use io;
+use router;
+use shutdown;
+use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+
+
+/// Loop statistics message name (ASCII: LOOP)
+pub const LOOP_NAME: [u8;4] = [76, 79, 79, 80];
+
+/// Loop statistics message size (bytes)
+pub const SIZE_OF_LOOP: usize = 28;
+
+/// How often to report loop statistics [ns]
+pub const LOOP_STATS_INTERVAL: u64 = 1000000000;
+
+
+/// Something that deals with incoming messages.
+pub trait Handler {
+
+    /// Can this handler use the message? A handler should check the message
+    /// is the right size here. If no message in a packet is accepted then
+    /// the packet is logged as unknown.
+    ///
+    /// The default accepts everything.
+    fn accepts(&self, message: &router::Message) -> bool {
+        let _ = message;
+        true
+    }
+
+    /// Deal with a message.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **flight**: What handlers and timers share
+    /// - **flight_computer**: Where to log and send data
+    /// - **message**: The message
+    ///
+    fn handle(&mut self, flight: &mut flight::Flight, flight_computer: &mut io::FC, message: &router::Message);
+}
+
+
+/// Something that runs every so often.
+pub trait Timer {
+
+    /// Run.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **flight**: What handlers and timers share
+    /// - **flight_computer**: Where to log and send data
+    /// - **now**: Time the timer fired
+    ///
+    fn fire(&mut self, flight: &mut flight::Flight, flight_computer: &mut io::FC, now: time::Duration);
+}
+
+
+impl<F> Timer for F where F: FnMut(&mut flight::Flight, &mut io::FC, time::Duration) {
+    fn fire(&mut self, flight: &mut flight::Flight, flight_computer: &mut io::FC, now: time::Duration) {
+        self(flight, flight_computer, now)
+    }
+}
+
+
+/// How well the loop is keeping up.
+///
+/// Latency is from when the loop woke up with a packet to when a handler was
+/// called with a message from it. Lateness is from when a timer was due to
+/// when it fired.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct LoopStats {
+
+    /// Messages passed to handlers
+    pub messages: u32,
+
+    /// Shortest wake up to dispatch latency [µs]
+    pub latency_min: u32,
+
+    /// Average wake up to dispatch latency [µs]
+    pub latency_mean: u32,
+
+    /// Longest wake up to dispatch latency [µs]
+    pub latency_max: u32,
+
+    /// Timers fired
+    pub timers: u32,
+
+    /// Average timer lateness [µs]
+    pub late_mean: u32,
+
+    /// Worst timer lateness [µs]
+    pub late_max: u32,
+}
+
+
+impl LoopStats {
+
+    /// Read loop statistics back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> LoopStats {
+        let mut message = Cursor::new(message_buffer);
+
+        LoopStats {
+            messages:     message.read_u32::<BigEndian>().unwrap(),
+            latency_min:  message.read_u32::<BigEndian>().unwrap(),
+            latency_mean: message.read_u32::<BigEndian>().unwrap(),
+            latency_max:  message.read_u32::<BigEndian>().unwrap(),
+            timers:       message.read_u32::<BigEndian>().unwrap(),
+            late_mean:    message.read_u32::<BigEndian>().unwrap(),
+            late_max:     message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_LOOP] {
+        let mut buffer = [0u8; SIZE_OF_LOOP];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u32::<BigEndian>(self.messages).unwrap();
+            message.write_u32::<BigEndian>(self.latency_min).unwrap();
+            message.write_u32::<BigEndian>(self.latency_mean).unwrap();
+            message.write_u32::<BigEndian>(self.latency_max).unwrap();
+            message.write_u32::<BigEndian>(self.timers).unwrap();
+            message.write_u32::<BigEndian>(self.late_mean).unwrap();
+            message.write_u32::<BigEndian>(self.late_max).unwrap();
+        }
+        buffer
+    }
+}
+
+
+// Shortest, longest and total of some delays
+#[derive(Default)]
+struct Delays {
+    count: u32,
+    min: Option<time::Duration>,
+    max: time::Duration,
+    total: time::Duration,
+}
+
+
+impl Delays {
+
+    // Add a delay
+    fn add(&mut self, delay: time::Duration) {
+        self.count = self.count.wrapping_add(1);
+        if self.min.map(|min| delay < min).unwrap_or(true) {
+            self.min = Some(delay);
+        }
+        if delay > self.max {
+            self.max = delay;
+        }
+        self.total += delay;
+    }
+
+    // Average delay
+    fn mean(&self) -> time::Duration {
+        if self.count > 0 { self.total / self.count } else { time::Duration::new(0, 0) }
+    }
+}
+
+
+/// Which handler, as returned by `EventLoop::add_handler`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HandlerId(usize);
+
+
+// A timer and when it should next fire
+struct Scheduled {
+
+    // Time between firings
+    period: time::Duration,
+
+    // When it's next due, once the loop has started
+    next: Option<time::Duration>,
+
+    // What to run
+    timer: Box<dyn Timer>,
+}
+
+
+/// The main loop.
+///
+/// # Example
+///
+/// Drive the main loop with scripted ADIS packets and check what gets logged:
+///
+/// ```
+/// use rust_fc::{clock, devices, events, flight, io, log, transport};
+/// use std::time::Duration;
+///
+/// let clock = clock::ManualClock::new();
+/// let source = transport::MemorySource::new();
+/// let memory_log = transport::MemoryLog::new();
+/// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock.clone()),
+///     Box::new(source.clone()), Vec::new(), Box::new(memory_log.clone()));
+///
+/// // IMU data by name, or bare from the IMU's port. Log it, then update the
+/// // state vector with it.
+/// let mut events = events::EventLoop::new(Default::default());
+/// let logger = events.add_handler(Box::new(flight::Logger { name: devices::ADIS_NAME, size: devices::SIZE_OF_ADIS }));
+/// let imu = events.add_handler(Box::new(flight::Adis));
+/// for &handler in &[logger, imu] {
+///     events.route_name(devices::ADIS_NAME, handler);
+///     events.route_port(io::PSAS_ADIS_PORT, handler);
+/// }
+///
+/// // Packets with sequence numbers 0 and 2, so we missed one
+/// let from = "127.0.0.1:35020".parse().unwrap();
+/// let adis = [7u8; devices::SIZE_OF_ADIS];
+/// for seqn in &[0u8, 2] {
+///     let mut packet = vec![0, 0, 0, *seqn];
+///     packet.extend_from_slice(&adis);
+///     source.push(from, &packet);
+/// }
+///
+/// // Something we don't know about
+/// source.push("127.0.0.1:40000".parse().unwrap(), b"hello");
+///
+/// // A batch from the IMU's port that was cut off, which isn't bare IMU data
+/// let mut packet = vec![0, 0, 0, 3, 65, 68, 73, 83, 0, 0, 0, 0, 0, 0, 0, 24];
+/// packet.extend_from_slice(&adis[..20]);
+/// source.push(from, &packet);
+///
+/// // And IMU data with a PSAS header, from a different port
+/// let mut packet = vec![0, 0, 0, 0, 65, 68, 73, 83, 0, 0, 0, 0, 0, 0, 0, 24];
+/// packet.extend_from_slice(&adis);
+/// source.push("127.0.0.1:35099".parse().unwrap(), &packet);
+///
+/// // Then two samples at once, taken 1 ms apart
+/// let mut packet = vec![0, 0, 0, 1];
+/// for sent in &[0u8, 1] {
+///     packet.extend_from_slice(&[65, 68, 73, 83, 0, 0, 0, 0x0f * sent, 0x42 * sent, 0x40 * sent, 0, 24]);
+///     packet.extend_from_slice(&adis);
+/// }
+/// source.push("127.0.0.1:35099".parse().unwrap(), &packet);
+/// while !source.is_empty() {
+///     clock.advance(Duration::from_millis(1));
+///     events.step(&mut flight_computer);
+/// }
+///
+/// let bytes = memory_log.bytes();
+/// let records: Vec<log::Record> = log::Reader::new(&bytes[..]).map(|r| r.unwrap()).collect();
+/// let names: Vec<[u8; 4]> = records.iter().map(|r| r.name).collect();
+/// assert_eq!(names, vec![*b"SEQN", *b"ADIS", *b"STAT", *b"SEQE", *b"ADIS", *b"STAT", *b"RAWP", *b"RAWP",
+///                         *b"ADIS", *b"STAT", *b"ADIS", *b"STAT", *b"ADIS", *b"STAT"]);
+/// assert_eq!(&records[1].bytes[..], &adis[..]);
+/// assert_eq!(records[4].time, Duration::from_millis(2));
+/// assert_eq!(records[10].time, Duration::from_millis(5));
+/// assert_eq!(records[12].time, Duration::from_millis(6));
+/// assert_eq!(events.flight.sequences.expected(io::PSAS_ADIS_PORT), Some(3));
+/// assert_eq!(events.flight.sequences.stats(io::PSAS_ADIS_PORT).unwrap().lost, 1);
+/// ```
+pub struct EventLoop {
+
+    /// What handlers and timers share
+    pub flight: flight::Flight,
+
+    /// Registered handlers
+    handlers: Vec<Box<dyn Handler>>,
+
+    /// Which handlers each message goes to
+    router: router::Router<Vec<HandlerId>>,
+
+    /// Registered timers
+    timers: Vec<Scheduled>,
+
+    /// Wake up to dispatch latency this reporting period
+    latency: Delays,
+
+    /// Timer lateness this reporting period
+    lateness: Delays,
+
+    /// When we last reported loop statistics
+    last_report: time::Duration,
+
+    /// Have we started the reporting periods yet?
+    started: bool,
+}
+
+
+impl EventLoop {
+
+    /// A loop with nothing registered.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **flight**: Where to start from (a new flight, or one resumed from a
+    ///   checkpoint)
+    ///
+    pub fn new(flight: flight::Flight) -> EventLoop {
+        EventLoop {
+            flight,
+            handlers: Vec::new(),
+            router: router::Router::new(),
+            timers: Vec::new(),
+            latency: Default::default(),
+            lateness: Default::default(),
+            last_report: time::Duration::new(0, 0),
+            started: false,
+        }
+    }
+
+    /// Register a handler. It won't see any messages until it has a route.
+    ///
+    /// ## Returns:
+    ///
+    /// An id to route messages to the handler with.
+    pub fn add_handler(&mut self, handler: Box<dyn Handler>) -> HandlerId {
+        self.handlers.push(handler);
+        HandlerId(self.handlers.len() - 1)
+    }
+
+    /// Send messages with this name to a handler, whichever port they come
+    /// from.
+    pub fn route_name(&mut self, name: [u8; 4], handler: HandlerId) {
+        self.router.name_entry(name).push(handler);
+    }
+
+    /// Send bare messages (with no PSAS header) from this port to a handler.
+    pub fn route_port(&mut self, port: u16, handler: HandlerId) {
+        self.router.port_entry(port).push(handler);
+    }
+
+    /// Send messages with this name, but only from this port, to a handler.
+    pub fn route(&mut self, name: [u8; 4], port: u16, handler: HandlerId) {
+        self.router.entry(name, port).push(handler);
+    }
+
+    /// Register a timer. It first fires one period after the loop starts.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **period**: Time between firings
+    /// - **timer**: What to run
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{clock, events, flight, io, transport};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::time::Duration;
+    ///
+    /// let clock = clock::ManualClock::new();
+    /// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock.clone()),
+    ///     Box::new(transport::MemorySource::new()), Vec::new(), Box::new(transport::MemoryLog::new()));
+    ///
+    /// let fired = Rc::new(Cell::new(0));
+    /// let counter = fired.clone();
+    /// let mut events = events::EventLoop::new(Default::default());
+    /// events.every(Duration::from_millis(10), Box::new(move |_: &mut flight::Flight, _: &mut io::FC, _| {
+    ///     counter.set(counter.get() + 1);
+    /// }));
+    ///
+    /// for _ in 0..25 {
+    ///     events.step(&mut flight_computer);
+    ///     clock.advance(Duration::from_millis(1));
+    /// }
+    /// assert_eq!(fired.get(), 2);
+    ///
+    /// // Loop statistics count them too
+    /// let stats = events.report(Duration::from_secs(1)).unwrap();
+    /// assert_eq!(stats.timers, 2);
+    /// ```
+    pub fn every(&mut self, period: time::Duration, timer: Box<dyn Timer>) {
+        self.timers.push(Scheduled { period, next: None, timer });
+    }
+
+    /// Run until someone asks us to stop.
+    pub fn run(&mut self, flight_computer: &mut io::FC) {
+        while shutdown::requested().is_none() {
+            self.step(flight_computer);
+        }
+    }
+
+    /// Go once around the main loop.
+    ///
+    /// Wait for a packet and deal with it, run anything that's due, and send
+    /// out any telemetry that has been waiting too long.
+    pub fn step(&mut self, flight_computer: &mut io::FC) {
+
+        // Wait for a packet, but not past when the next timer or statistics
+        // are due
+        let deadline = self.next_due(flight_computer.time());
+        if let Some(packet) = flight_computer.listen_until(Some(deadline)) {
+            self.packet(flight_computer, &packet);
+        }
+
+        self.poll(flight_computer);
+    }
+
+    /// When a timer or statistics report is next due.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock. Timers that
+    ///   haven't started yet start counting from here.
+    ///
+    pub fn next_due(&mut self, now: time::Duration) -> time::Duration {
+        self.start(now);
+        let mut due = self.flight.sequences.next_report().min(self.flight.links.next_report()).min(self.next_report());
+        for scheduled in &mut self.timers {
+            due = due.min(*scheduled.next.get_or_insert(now + scheduled.period));
+        }
+        due
+    }
+
+    /// Run any timers that are due, report statistics if it's time, and send
+    /// out any telemetry that has been waiting too long.
+    pub fn poll(&mut self, flight_computer: &mut io::FC) {
+        let now = flight_computer.time();
+        self.start(now);
+        for scheduled in &mut self.timers {
+            let next = *scheduled.next.get_or_insert(now + scheduled.period);
+            if now >= next {
+                self.lateness.add(now - next);
+                scheduled.timer.fire(&mut self.flight, flight_computer, now);
+
+                // If we fell behind, don't try to catch up
+                let following = next + scheduled.period;
+                scheduled.next = Some(if following > now { following } else { now + scheduled.period });
+            }
+        }
+
+        self.flight.report_statistics(flight_computer);
+        if let Some(stats) = self.report(now) {
+            flight_computer.log_message(&stats.as_message(), LOOP_NAME, now, SIZE_OF_LOOP).unwrap();
+            flight_computer.telemetry(&stats.as_message(), LOOP_NAME, now, SIZE_OF_LOOP);
+        }
+
+        flight_computer.report_sends();
+
+        // Don't let telemetry sit around waiting for a full packet
+        flight_computer.poll_telemetry();
+    }
+
+    // Start the reporting periods for loop, sequence and link statistics the
+    // first time around the loop, like the timers. That way the first report
+    // after resuming from a checkpoint only covers time since we resumed.
+    fn start(&mut self, now: time::Duration) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        self.last_report = now;
+        self.flight.sequences.start(now);
+        self.flight.links.start(now);
+    }
+
+    /// When the next loop statistics are due.
+    pub fn next_report(&self) -> time::Duration {
+        self.last_report + time::Duration::from_nanos(LOOP_STATS_INTERVAL)
+    }
+
+    /// Loop statistics since the last report, if it's time for another one.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock
+    ///
+    pub fn report(&mut self, now: time::Duration) -> Option<LoopStats> {
+        if now < self.next_report() {
+            return None;
+        }
+        self.last_report = now;
+
+        let stats = LoopStats {
+            messages: self.latency.count,
+            latency_min: io::micros(self.latency.min.unwrap_or_default()),
+            latency_mean: io::micros(self.latency.mean()),
+            latency_max: io::micros(self.latency.max),
+            timers: self.lateness.count,
+            late_mean: io::micros(self.lateness.mean()),
+            late_max: io::micros(self.lateness.max),
+        };
+        self.latency = Default::default();
+        self.lateness = Default::default();
+        Some(stats)
+    }
+
+    // The handlers for a message that can use it, in the order they were
+    // registered
+    fn handlers(&self, message: &router::Message) -> Vec<HandlerId> {
+        match self.router.route(message.name, message.port) {
+            Some(ids) => ids.iter().cloned().filter(|id| self.handlers[id.0].accepts(message)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Pass one message to its handlers, without any sequence number checks.
+    /// This is how a replay feeds in messages from a log.
+    ///
+    /// ## Returns:
+    ///
+    /// True if any handler took the message.
+    pub fn dispatch(&mut self, flight_computer: &mut io::FC, message: &router::Message) -> bool {
+        let ids = self.handlers(message);
+        for id in &ids {
+            self.handlers[id.0].handle(&mut self.flight, flight_computer, message);
+        }
+        !ids.is_empty()
+    }
+
+    /// Deal with a packet from the network.
+    ///
+    /// Every message in the packet is passed on to its handlers. If no
+    /// handler can use any of them we log the whole packet so we can work
+    /// out what sent it later.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **flight_computer**: Where to log and send data
+    /// - **packet**: The packet
+    ///
+    pub fn packet(&mut self, flight_computer: &mut io::FC, packet: &io::Packet) {
+
+        let messages = router::messages(packet);
+        let port = packet.from.port();
+
+        // Unknown Message Type
+        // ====================
+        // We don't know what this is. Log it (if we haven't logged too many
+        // already) so we can work out what sent it. This is only a clue, so
+        // if it can't be logged it's counted as unlogged and we carry on.
+        if messages.iter().all(|message| self.handlers(message).is_empty()) {
+            let logged = flight_computer.log_raw_packet(packet).unwrap_or(false);
+            self.flight.links.record_unknown(port, packet.time, logged);
+            return;
+        }
+
+        // We expect monotonically increasing sequence numbers. Anything
+        // that isn't the next one gets logged.
+        let (sequence, seqerror) = self.flight.sequences.check(port, messages[0].seqn);
+        self.flight.links.record(port, packet.time, sequence);
+        if let Some(seqerror) = seqerror {
+            flight_computer.log_message(&seqerror.as_message(), io::SEQE_NAME, packet.time, io::SIZE_OF_SEQE).unwrap();
+            flight_computer.telemetry(&seqerror.as_message(), io::SEQE_NAME, packet.time, io::SIZE_OF_SEQE);
+        }
+
+        // Duplicate and late packets are ignored. Real time systems can't do
+        // anything with stale data!
+        if !sequence.is_new() {
+            return;
+        }
+
+        for message in &messages {
+            let woke = flight_computer.time().checked_sub(packet.time).unwrap_or_default();
+            if self.dispatch(flight_computer, message) {
+                self.latency.add(woke);
+            }
+            // Something else in with messages we know about. Keep it, in
+            // case it's useful.
+            else if let Some(name) = message.name {
+                flight_computer.log_message(message.payload, name, message.time, message.payload.len()).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+/*! # Flight
+
+The handlers and timers that make up the flight computer, and what they
+share. For IMU data we log it, update the state vector and log that too. A
+timer runs the controller and drives the fins, and another saves a
+checkpoint in case we crash. Every so often we also log and telemeter
+statistics on how well packets are getting through: running totals from the
+sequence numbers, and the rate and timing of packets from each source.
+
+Each of those is registered with the `events` module on its own and keeps
+its own state, so they can be swapped out or left out (a replay doesn't save
+checkpoints, for instance). Keeping this out of `main` means it can be driven
+from tests (with the in-memory transports) and from a replay of an old log,
+as well as from the network.
+*/
+
+use std::time;
+use actuator;
+use config;
+use devices;
+use events;
+use io;
+use state;
+use control;
+use recovery;
+use router;
+
+
+/// What handlers and timers share.
+///
+/// Handlers and timers registered with the `events::EventLoop` keep their own
+/// state (the controller lives in `ControlTimer`, for instance) and all work
+/// on the same `Flight` for the rest.
+#[derive(Default)]
+pub struct Flight {
+
+    /// State vector
+    pub state: state::State,
+
+    /// Sequence numbers of everything we receive
+    pub sequences: io::SequenceTracker,
+
+    /// How packets are arriving from each source
+    pub links: io::LinkMonitor,
+}
+
+
+// Is a message the right size? Named messages have to be exactly the size,
+// bare ones at least (older devices pad them out).
+fn fits(message: &router::Message, size: usize) -> bool {
+    match message.name {
+        Some(_) => message.payload.len() == size,
+        None => message.payload.len() >= size,
+    }
+}
+
+
+/// Handler that logs messages as they are and sends them over telemetry.
+///
+/// Bare messages are logged under `name`, cut down to `size`.
+pub struct Logger {
+
+    /// Name to log messages under
+    pub name: [u8; 4],
+
+    /// Message size (bytes)
+    pub size: usize,
+}
+
+
+impl events::Handler for Logger {
+    fn accepts(&self, message: &router::Message) -> bool {
+        fits(message, self.size)
+    }
+
+    fn handle(&mut self, _: &mut Flight, flight_computer: &mut io::FC, message: &router::Message) {
+        let payload = &message.payload[..self.size];
+        flight_computer.log_message(payload, self.name, message.time, self.size).unwrap();
+        flight_computer.telemetry(payload, self.name, message.time, self.size);
+    }
+}
+
+
+/// Handler that updates the state vector from ADIS IMU data, and logs the
+/// new state and sends it over telemetry.
+///
+/// Takes messages named `ADIS` that are exactly the right size, or bare IMU
+/// data that's at least big enough (older IMUs pad it out). This doesn't log
+/// the IMU data itself, register a `Logger` for that as well.
+pub struct Adis;
+
+
+impl events::Handler for Adis {
+    fn accepts(&self, message: &router::Message) -> bool {
+        fits(message, devices::SIZE_OF_ADIS)
+    }
+
+    fn handle(&mut self, flight: &mut Flight, flight_computer: &mut io::FC, message: &router::Message) {
+
+        // Unpack binary message into proper values with units
+        let adis = devices::recv_adis(&message.payload[..devices::SIZE_OF_ADIS]);
+
+        // Since this is IMU data, we need to update the state vector
+        flight.state.update_imu(message.time, adis);
+
+        let stat = flight.state.as_message();
+        flight_computer.log_message(&stat, state::STATE_NAME, message.time, state::SIZE_OF_STATE).unwrap();
+        flight_computer.telemetry(&stat, state::STATE_NAME, message.time, state::SIZE_OF_STATE);
+    }
+}
+
+
+/// Timer that runs the controller, sends the fin angle to the actuator, and
+/// logs what both did.
+///
+/// Outside the control window the fins go to neutral. If we haven't had IMU
+/// data for a while the state is just a guess, so rather than steer with it
+/// we stop sending fin commands (the fins stay where they were) until fresh
+/// data arrives. The controller still logs that it's holding.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{actuator, clock, config, flight, io, state, transport};
+/// use rust_fc::events::Timer;
+/// use std::time::Duration;
+///
+/// let clock = clock::ManualClock::new();
+/// let fins = transport::MemorySink::new();
+/// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock.clone()),
+///     Box::new(transport::MemorySource::new()), Vec::new(), Box::new(transport::MemoryLog::new()));
+/// flight_computer.set_actuator(Box::new(fins.clone()));
+///
+/// let config: config::Config = Default::default();
+/// let mut timer = flight::ControlTimer::new(&config);
+/// let mut flight: flight::Flight = Default::default();
+///
+/// // Fresh IMU data after burnout, so the controller runs
+/// flight.state.time = 5000000;
+/// flight.state.phase = state::Phase::Coast;
+/// flight.state.vel_up = 150.0;
+/// flight.state.roll_rate = 10.0;
+/// timer.fire(&mut flight, &mut flight_computer, Duration::from_millis(10));
+/// assert!(timer.controller.active);
+/// assert!(timer.controller.correction < 0.0);
+/// assert_eq!(fins.packets().len(), 1);
+/// let roll = actuator::Roll::from_message(&fins.packets()[0][4 + io::HEADER_SIZE..]);
+/// assert!(roll.angle < 0.0);
+///
+/// // Then nothing for a while, so the fins are left where they are
+/// timer.fire(&mut flight, &mut flight_computer, Duration::from_millis(100));
+/// assert!(!timer.controller.active);
+/// assert_eq!(timer.controller.correction, 0.0);
+/// assert_eq!(fins.packets().len(), 1);
+/// assert_eq!(timer.actuator.angle, roll.angle);
+///
+/// // Spinning far faster than the fins can correct
+/// flight.state.time = 110000000;
+/// flight.state.roll_rate = 1000.0;
+/// timer.fire(&mut flight, &mut flight_computer, Duration::from_millis(110));
+/// let roll = actuator::Roll::from_message(&fins.packets()[1][4 + io::HEADER_SIZE..]);
+/// assert!(roll.saturated);
+/// assert!(roll.requested < config.actuator.travel_min);
+/// assert!(roll.angle >= config.actuator.travel_min);
+/// ```
+pub struct ControlTimer {
+
+    /// Time between steps, assumed for the first step
+    pub period: time::Duration,
+
+    /// How old the state can be before we stop actuating
+    pub stale: time::Duration,
+
+    /// Controller
+    pub controller: control::Control,
+
+    /// Fins
+    pub actuator: actuator::Actuator,
+
+    /// When we last ran
+    last: Option<time::Duration>,
+}
+
+
+impl ControlTimer {
+
+    /// A control timer, controller and actuator with settings from the
+    /// configuration.
+    pub fn new(config: &config::Config) -> ControlTimer {
+        ControlTimer {
+            period: config.control.period(),
+            stale: config.control.stale,
+            controller: control::Control::new(&config.control),
+            actuator: actuator::Actuator::new(&config.actuator),
+            last: None,
+        }
+    }
+}
+
+
+impl events::Timer for ControlTimer {
+    fn fire(&mut self, flight: &mut Flight, flight_computer: &mut io::FC, now: time::Duration) {
+
+        // Use the time that actually passed, in case we fired late
+        let dt = match self.last {
+            Some(last) => now.checked_sub(last).unwrap_or_default(),
+            None => self.period,
+        };
+        self.last = Some(now);
+
+        let state = &flight.state;
+        let age = now.checked_sub(time::Duration::from_nanos(state.time)).unwrap_or_default();
+        let dt = dt.as_secs() as f64 + dt.subsec_nanos() as f64 / 1e9;
+
+        // A state time of zero means we've never had any IMU data. Either
+        // way there's nothing to steer with, so leave the fins alone.
+        if state.time == 0 || age > self.stale {
+            let terms = self.controller.hold(state, dt);
+            flight_computer.log_message(&terms.as_message(), control::CONTROL_NAME, now, control::SIZE_OF_CONTROL).unwrap();
+            flight_computer.telemetry(&terms.as_message(), control::CONTROL_NAME, now, control::SIZE_OF_CONTROL);
+            return;
+        }
+
+        let (min, max) = self.actuator.reach(dt);
+        let terms = if !self.controller.window.open(state) {
+            self.controller.disable(state, dt)
+        }
+        else {
+            self.controller.pid_within(state, dt, min, max)
+        };
+
+        // Even going back to neutral is limited by how fast the fins turn.
+        // The controller has already limited its output to what the fins can
+        // reach, so the ROLL message says what it wanted before that.
+        let mut roll = self.actuator.command(terms.output, dt);
+        roll.requested = terms.requested;
+        roll.saturated |= terms.saturated;
+
+        flight_computer.log_message(&terms.as_message(), control::CONTROL_NAME, now, control::SIZE_OF_CONTROL).unwrap();
+        flight_computer.telemetry(&terms.as_message(), control::CONTROL_NAME, now, control::SIZE_OF_CONTROL);
+        flight_computer.log_message(&roll.as_message(), actuator::ROLL_NAME, now, actuator::SIZE_OF_ROLL).unwrap();
+        flight_computer.telemetry(&roll.as_message(), actuator::ROLL_NAME, now, actuator::SIZE_OF_ROLL);
+        flight_computer.actuate(&roll.as_message(), actuator::ROLL_NAME, now, actuator::SIZE_OF_ROLL);
+    }
+}
+
+
+/// Timer that saves a checkpoint, so we can pick up where we left off if we
+/// crash.
+///
+/// Saving is best effort: if the disk fills up or goes away it's better to
+/// keep flying without checkpoints than to stop. Failures are counted, and
+/// we say so on the console when they start and when they stop.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{clock, flight, io, transport};
+/// use rust_fc::events::Timer;
+/// use std::time::Duration;
+///
+/// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock::ManualClock::new()),
+///     Box::new(transport::MemorySource::new()), Vec::new(), Box::new(transport::MemoryLog::new()));
+///
+/// let mut checkpointer = flight::Checkpointer::new("/nonexistent/fc-checkpoint");
+/// checkpointer.fire(&mut Default::default(), &mut flight_computer, Duration::from_millis(100));
+/// checkpointer.fire(&mut Default::default(), &mut flight_computer, Duration::from_millis(200));
+/// assert_eq!(checkpointer.failures, 2);
+/// ```
+pub struct Checkpointer {
+
+    /// Where to save checkpoints
+    pub path: String,
+
+    /// How many checkpoints we've failed to save
+    pub failures: u32,
+
+    /// Did the last one fail?
+    failing: bool,
+}
+
+
+impl Checkpointer {
+
+    /// A timer that saves checkpoints to a file.
+    pub fn new(path: &str) -> Checkpointer {
+        Checkpointer {
+            path: path.to_string(),
+            failures: 0,
+            failing: false,
+        }
+    }
+}
+
+
+impl events::Timer for Checkpointer {
+    fn fire(&mut self, flight: &mut Flight, flight_computer: &mut io::FC, now: time::Duration) {
+        let checkpoint = recovery::Checkpoint {
+            time: now,
+            log_number: flight_computer.log_number(),
+            log_size: flight_computer.log_size(),
+            telemetry_seqn: flight_computer.sequence_number(),
+            actuator_seqn: flight_computer.actuator_sequence_number(),
+            sequences: flight.sequences.expected_by_port(),
+            state: flight.state.clone(),
+        };
+        match checkpoint.save(&self.path) {
+            Ok(_) => {
+                if self.failing {
+                    println!(" Saving checkpoints again after {} failures", self.failures);
+                }
+                self.failing = false;
+            },
+            Err(e) => {
+                if !self.failing {
+                    println!(" Error saving checkpoint, carrying on without: {}", e);
+                }
+                self.failures = self.failures.saturating_add(1);
+                self.failing = true;
+            },
+        }
+    }
+}
+
+
+impl Flight {
+
+    /// Pick up where we left off after a crash.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **checkpoint**: The last checkpoint written before the crash
+    ///
+    pub fn resume(checkpoint: &recovery::Checkpoint) -> Flight {
+        let mut flight = Flight {
+            state: checkpoint.state.clone(),
+            ..Default::default()
+        };
+
+        // Start integrating again from the first sample after the restart
+        flight.state.integrating = false;
+        for &(port, expected) in &checkpoint.sequences {
+            flight.sequences.resume(port, expected);
+        }
+        flight
+    }
+
+    /// Log and send sequence and link statistics for each source, if it's
+    /// time.
+    pub fn report_statistics(&mut self, flight_computer: &mut io::FC) {
+        let now = flight_computer.time();
+        for stats in self.sequences.report(now) {
+            flight_computer.log_message(&stats.as_message(), io::SEQS_NAME, now, io::SIZE_OF_SEQS).unwrap();
+            flight_computer.telemetry(&stats.as_message(), io::SEQS_NAME, now, io::SIZE_OF_SEQS);
+        }
+        for stats in self.links.report(now) {
+            flight_computer.log_message(&stats.as_message(), io::LINK_NAME, now, io::SIZE_OF_LINK).unwrap();
+            flight_computer.telemetry(&stats.as_message(), io::LINK_NAME, now, io::SIZE_OF_LINK);
+        }
+    }
+}
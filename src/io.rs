@@ -13,14 +13,28 @@ In many ways this is the guts of the flight computer.
 
 extern crate byteorder;
 
-use std::net::UdpSocket;
+use std::net::SocketAddr;
 use std::net::SocketAddrV4;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::IpAddr;
 use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Cursor;
+use std::collections::HashMap;
 use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
 use std::time;
+use clock;
+use config;
+use recovery;
+use shutdown;
+use telemetry;
+use transport;
 
 
 use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
@@ -28,7 +42,7 @@ use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
 /// Ports for data
 const PSAS_LISTEN_UDP_PORT: u16 = 36000;
 
-/// Port for outgoing telemetry
+/// Port for outgoing telemetry (when no destinations are configured)
 const PSAS_TELEMETRY_UDP_PORT: u16 = 35001;
 
 /// Expected port for ADIS messages
@@ -37,11 +51,15 @@ pub const PSAS_ADIS_PORT: u16 = 35020;
 /// Maximum size of single telemetry packet
 const P_LIMIT: usize = 1432;
 
+/// Biggest datagram we can receive (as much as UDP can carry), so nothing we
+/// receive is ever cut short
+const DATAGRAM_LIMIT: usize = 65535;
+
 /// Size of PSAS Packet header
-const HEADER_SIZE: usize = 12;
+pub const HEADER_SIZE: usize = 12;
 
 /// Message name (ASCII: SEQN)
-const SEQN_NAME: [u8;4] = [83, 69, 81, 78];
+pub const SEQN_NAME: [u8;4] = [83, 69, 81, 78];
 
 /// Sequence Error message size (bytes)
 pub const SIZE_OF_SEQE: usize = 10;
@@ -49,16 +67,32 @@ pub const SIZE_OF_SEQE: usize = 10;
 /// Sequence Error message name (ASCII: SEQE)
 pub const SEQE_NAME: [u8;4] = [83, 69, 81, 69];
 
+/// Raw packet message name (ASCII: RAWP)
+pub const RAW_PACKET_NAME: [u8;4] = [82, 65, 87, 80];
+
+/// Size of a raw packet message without the payload (bytes)
+pub const SIZE_OF_RAW_PACKET: usize = 20;
+
+/// Most of a packet's payload we keep in a raw packet message (bytes)
+pub const RAW_PACKET_PAYLOAD_LIMIT: usize = 64;
+
+/// Budget for logging raw packets [bytes/s]
+pub const RAW_PACKET_RATE: u64 = 4096;
+
+/// How far over the raw packet budget we can burst [bytes]
+pub const RAW_PACKET_BURST: u64 = 16384;
+
 
 /// Flight Computer IO.
 ///
 /// Internally holds state for this implementation of the flight computer.
-/// This includes time (nanosecond counter from startup), a socket for
-/// listening for incoming data, a socket for sending data over the telemetry
-/// link, a log file, a running count of telemetry messages sent and a buffer
-/// for partly built telemetry messages.
+/// This includes the clock (nanosecond counter from startup), where incoming data
+/// comes from, where telemetry goes, the log, a running count of telemetry
+/// messages sent and a buffer for partly built telemetry messages.
 ///
-/// To initialize use the Default trait:
+/// To initialize use the Default trait (or `FC::new` with a configuration),
+/// or `FC::resume` to pick up from a checkpoint after a crash. To run without
+/// the network (in tests) use `FC::with_transport`:
 ///
 /// # Example
 ///
@@ -69,23 +103,74 @@ pub const SEQE_NAME: [u8;4] = [83, 69, 81, 69];
 /// ```
 pub struct FC {
 
-    /// Instant we started
-    boot_time: time::Instant,
+    /// Where we get the time from
+    clock: Box<dyn clock::Clock>,
+
+    /// Where messages come from (none when replaying a log).
+    source: Option<Box<dyn transport::DatagramSource>>,
+
+    /// Where to send telemetry.
+    telemetry_sinks: Vec<Box<dyn transport::TelemetrySink>>,
 
-    /// Socket to listen on for messages.
-    fc_listen_socket: UdpSocket,
+    /// Where to write the log.
+    log: Box<dyn transport::LogSink>,
 
-    /// Socket to send telemetry.
-    telemetry_socket: UdpSocket,
+    /// Which log file we're writing (the `NNN` in `logfile-NNN`).
+    log_number: u16,
 
-    /// File to write data to.
-    fc_log_file: File,
+    /// How many bytes of whole records we've written to the log.
+    log_size: u64,
 
     /// Current count of telemetry messages sent.
     sequence_number: u32,
 
     /// Buffer of messages to build a telemetry Packet.
     telemetry_buffer: Vec<u8>,
+
+    /// Where each message in the telemetry buffer starts and ends, and how
+    /// important it is.
+    telemetry_records: Vec<(usize, usize, telemetry::Priority)>,
+
+    /// Telemetry policy for each message type.
+    telemetry_policies: HashMap<[u8; 4], telemetry::Policy>,
+
+    /// How many of each message type we've been asked to send (for
+    /// decimation).
+    telemetry_counts: HashMap<[u8; 4], u32>,
+
+    /// Bandwidth budget for the telemetry link.
+    telemetry_bucket: telemetry::TokenBucket,
+
+    /// Count of telemetry messages dropped for being over budget.
+    telemetry_dropped: u64,
+
+    /// When the oldest message in the telemetry buffer was queued.
+    telemetry_queued: Option<time::Duration>,
+
+    /// How long a message can wait in the telemetry buffer.
+    telemetry_max_age: time::Duration,
+
+    /// Budget for logging packets we don't recognize.
+    raw_packet_bucket: telemetry::TokenBucket,
+
+    /// Where to send fin commands, if anywhere.
+    actuator: Option<Box<dyn transport::TelemetrySink>>,
+
+    /// Current count of packets sent to the actuator.
+    actuator_sequence_number: u32,
+
+    /// Where incoming datagrams are received into.
+    receive_buffer: Vec<u8>,
+
+    /// How sending to each telemetry destination went this reporting
+    /// period, in the same order as `telemetry_sinks`.
+    telemetry_sends: Vec<SendStats>,
+
+    /// How sending to the actuator went this reporting period.
+    actuator_sends: SendStats,
+
+    /// When we last reported send statistics.
+    last_send_report: time::Duration,
 }
 
 
@@ -98,7 +183,7 @@ fn pack_header(name: [u8; 4], time: time::Duration, message_size: usize) -> [u8;
 
         // Fields:
         // ID (Four character code)
-        header.write(&name).unwrap();
+        header.write_all(&name).unwrap();
 
         // Timestamp, 6 bytes nanoseconds from boot
         let nanos: u64 = (time.as_secs() * 1000000000) + time.subsec_nanos() as u64;
@@ -108,7 +193,7 @@ fn pack_header(name: [u8; 4], time: time::Duration, message_size: usize) -> [u8;
             t.write_u64::<BigEndian>(nanos).unwrap();
         }
         // Truncate to 6 least significant bytes
-        header.write(&time_buffer[2..8]).unwrap();
+        header.write_all(&time_buffer[2..8]).unwrap();
 
         // Size:
         header.write_u16::<BigEndian>(message_size as u16).unwrap();
@@ -117,87 +202,409 @@ fn pack_header(name: [u8; 4], time: time::Duration, message_size: usize) -> [u8;
 }
 
 
-impl Default for FC {
-    fn default () -> FC {
+/// Read a PSAS message header.
+///
+/// This is the inverse of how headers are packed for the log and telemetry:
+/// a four character name, a 6 byte timestamp (nanoseconds from boot) and the
+/// size of the message that follows.
+///
+/// ## Parameters:
+///
+/// - **buffer**: A buffer of bytes at least as long as a header
+///   (`HEADER_SIZE`).
+///
+/// ## Returns:
+///
+/// A tuple of the message name, time and message size.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::io;
+///
+/// let header = [83, 69, 81, 78, 0, 0, 0, 0, 0, 100, 0, 4];
+/// let (name, time, size) = io::unpack_header(&header);
+///
+/// assert_eq!(&name, b"SEQN");
+/// assert_eq!(time.subsec_nanos(), 100);
+/// assert_eq!(size, 4);
+/// ```
+pub fn unpack_header(buffer: &[u8]) -> ([u8; 4], time::Duration, usize) {
+
+    let mut header = Cursor::new(buffer);
+
+    // Fields:
+    // ID (Four character code)
+    let mut name = [0u8; 4];
+    name.copy_from_slice(&buffer[0..4]);
+    header.set_position(4);
+
+    // Timestamp, 6 bytes nanoseconds from boot
+    let nanos = header.read_uint::<BigEndian>(6).unwrap();
+
+    // Size:
+    let message_size = header.read_u16::<BigEndian>().unwrap() as usize;
+
+    (name, time::Duration::new(nanos / 1000000000, (nanos % 1000000000) as u32), message_size)
+}
+
+
+// Name of a log file
+fn log_filename(log_number: u16) -> String {
+    format!("logfile-{:03}", log_number)
+}
+
+
+// Try and open log file, loop until we find a name that's not taken
+fn new_log_file() -> (File, u16) {
 
-        // Boot time
-        let boot_time = time::Instant::now();
+    let mut newfilenum = 0;
+    while File::open(log_filename(newfilenum)).is_ok() {
+        newfilenum += 1;
+    }
+
+    // We got here, so open the file
+    match File::create(log_filename(newfilenum)) {
+        Ok(file) => (file, newfilenum),
+        Err(e) => { panic!("{}", e) },
+    }
+}
 
-        let fc_listen_socket: UdpSocket;
-        let telemetry_socket: UdpSocket;
-        let fc_log_file: File;
 
-        // Try and open listen socket
-        match UdpSocket::bind(("0.0.0.0", PSAS_LISTEN_UDP_PORT)) {
-            Ok(socket) => { fc_listen_socket = socket; },
-            Err(e) => { panic!(e) },
+// Where the whole records in a log end. We know everything up to `from` is
+// whole records, so only the headers after it need reading. A crash can leave
+// part of a record at the end.
+fn complete_records(file: &mut File, from: u64) -> Result<u64, Error> {
+    let length = file.metadata()?.len();
+    if from > length {
+        return Err(Error::new(ErrorKind::InvalidData, "log is shorter than the checkpoint says"));
+    }
+    let mut offset = from;
+    let mut header = [0u8; HEADER_SIZE];
+    while length - offset >= HEADER_SIZE as u64 {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let (_, _, size) = unpack_header(&header);
+        if length - offset - (HEADER_SIZE as u64) < size as u64 {
+            break;
         }
+        offset += (HEADER_SIZE + size) as u64;
+    }
+    Ok(offset)
+}
+
 
-        // Try and open telemetry socket
-        match UdpSocket::bind("0.0.0.0:0") {
-            Ok(socket) => { telemetry_socket = socket; },
-            Err(e) => { panic!(e) },
+// Open a log file to carry on writing it after a crash. Anything after the
+// last whole record is cut off first, otherwise everything we add would be
+// misread. Returns the file and how long it is now.
+fn reopen_log_file(log_number: u16, log_size: u64) -> Result<(File, u64), Error> {
+    let mut file = OpenOptions::new().read(true).append(true).open(log_filename(log_number))?;
+    let size = complete_records(&mut file, log_size)?;
+    file.set_len(size)?;
+    Ok((file, size))
+}
+
+
+// Open the listen sockets and a socket for each telemetry destination. With
+// no destinations configured we send to a ground station on this machine.
+fn open_udp(config: &config::Config) -> (Box<dyn transport::DatagramSource>, Vec<Box<dyn transport::TelemetrySink>>) {
+
+    // Try and open listen sockets
+    let mut listen = config.network.listen.clone();
+    if listen.is_empty() {
+        listen.push(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), PSAS_LISTEN_UDP_PORT)));
+    }
+    let listener = match transport::UdpListener::bind(&listen) {
+        Ok(listener) => listener,
+        Err(e) => { panic!("{}", e) },
+    };
+
+    // Try and open telemetry sockets
+    let mut destinations = config.telemetry.destinations.clone();
+    if destinations.is_empty() {
+        destinations.push(telemetry::Destination {
+            addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), PSAS_TELEMETRY_UDP_PORT)),
+            ttl: None,
+            interface: None,
+        });
+    }
+    let mut telemetry_sinks: Vec<Box<dyn transport::TelemetrySink>> = Vec::new();
+    for destination in destinations {
+        match destination.open() {
+            Ok(socket) => { telemetry_sinks.push(Box::new(transport::UdpSink { addr: destination.addr, socket })); },
+            Err(e) => { panic!("{}: {}", destination.addr, e) },
         }
+    }
+
+    (Box::new(listener), telemetry_sinks)
+}
 
 
-        // Try and open log file, loop until we find a name that's not taken
-        let mut newfilenum = 0;
-        loop {
-            let filename = format!("logfile-{:03}", newfilenum);
-            match File::open(filename) {
-                // If this works, keep going
-                Ok(_) => { newfilenum += 1; },
-                // If this fails, make a new file
-                Err(_) => { break; }
-            }
+// Open a socket for the roll actuator, if we have one
+fn open_actuator(config: &config::Config) -> Option<Box<dyn transport::TelemetrySink>> {
+    config.actuator.destination.as_ref().map(|destination| {
+        match destination.open() {
+            Ok(socket) => Box::new(transport::UdpSink { addr: destination.addr, socket }) as Box<dyn transport::TelemetrySink>,
+            Err(e) => { panic!("{}: {}", destination.addr, e) },
         }
+    })
+}
+
+
+impl Default for FC {
+    fn default () -> FC {
+        FC::new(&Default::default())
+    }
+}
+
+
+impl FC {
+
+    /// Start up a new flight computer.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **config**: Flight computer configuration
+    ///
+    pub fn new(config: &config::Config) -> FC {
+
+        let (fc_log_file, log_number) = new_log_file();
+        let (source, telemetry_sinks) = open_udp(config);
+
+        let mut fc = FC::open(config, Box::new(clock::SystemClock::new()), Some(source), telemetry_sinks, Box::new(fc_log_file), log_number, 0);
+        fc.actuator = open_actuator(config);
+
+        // Write log header
+        fc.log_message(&[0, 0, 0, 0], SEQN_NAME, time::Duration::new(0, 0), 4).unwrap();
+
+        fc
+    }
+
+    /// Resume after a crash.
+    ///
+    /// Rather than starting a new log, this keeps appending to the log file
+    /// we were writing before (after cutting off any partly written record
+    /// at the end, which only means reading what was written since the
+    /// checkpoint) and carries on counting telemetry sequence numbers from
+    /// where we left off. The clock keeps running from the checkpoint time
+    /// plus however long we were down, so timestamps in the log never go
+    /// backwards. A restart message is logged to mark the gap. If the old
+    /// log can't be opened we start a new one, with a header as usual.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **config**: Flight computer configuration
+    /// - **checkpoint**: The last checkpoint written before the crash
+    /// - **downtime**: How long we were down for
+    ///
+    pub fn resume(config: &config::Config, checkpoint: &recovery::Checkpoint, downtime: time::Duration) -> FC {
+
+        let (fc_log_file, log_number, log_size) = match reopen_log_file(checkpoint.log_number, checkpoint.log_size) {
+            Ok((file, size)) => (file, checkpoint.log_number, size),
+            Err(_) => {
+                let (file, number) = new_log_file();
+                (file, number, 0)
+            },
+        };
+
+        let (source, telemetry_sinks) = open_udp(config);
+
+        let clock = clock::SystemClock::starting_at(checkpoint.time + downtime);
 
-        // We got here, so open the file
-        match File::create(format!("logfile-{:03}", newfilenum)) {
-            Ok(file) => { fc_log_file = file; },
-            Err(e) => { panic!(e) },
+        let mut fc = FC::open(config, Box::new(clock), Some(source), telemetry_sinks, Box::new(fc_log_file), log_number, checkpoint.telemetry_seqn);
+        fc.log_size = log_size;
+        fc.actuator = open_actuator(config);
+
+        // If we couldn't pick up the old log, the new one needs a header
+        // like any other
+        if log_size == 0 {
+            fc.log_message(&[0, 0, 0, 0], SEQN_NAME, time::Duration::new(0, 0), 4).unwrap();
         }
 
-        // Put first sequence number (always 0) in the telemetry buffer.
-        let mut telemetry_buffer = Vec::with_capacity(P_LIMIT);
-        telemetry_buffer.extend_from_slice(&[0, 0, 0, 0]);
+        // We kept sending fin commands after the checkpoint was saved. Skip
+        // well past those, so the actuator doesn't take new ones for repeats.
+        let since_checkpoint = config.control.rate as u64 * recovery::CHECKPOINT_INTERVAL / 1000000000;
+        fc.actuator_sequence_number = checkpoint.actuator_seqn.wrapping_add(2 * since_checkpoint as u32 + 1);
 
-        // Initialise
-        let mut fc = FC {
-            boot_time: boot_time,
-            fc_listen_socket: fc_listen_socket,
-            telemetry_socket: telemetry_socket,
-            fc_log_file: fc_log_file,
-            sequence_number: 0,
-            telemetry_buffer: telemetry_buffer,
+        let restart = recovery::Restart {
+            downtime: (downtime.as_secs() * 1000000000) + downtime.subsec_nanos() as u64,
+            resumed_from: (checkpoint.time.as_secs() * 1000000000) + checkpoint.time.subsec_nanos() as u64,
         };
+        fc.event(&restart.as_message(), recovery::RESTART_NAME, recovery::SIZE_OF_RESTART).unwrap();
+
+        fc
+    }
+
+    /// Start up a flight computer to replay a log.
+    ///
+    /// This writes a new log just like `FC::new`, but doesn't open any
+    /// sockets: messages come from the old log instead of the network, and
+    /// nothing gets sent to the ground. Set the clock to the time of each
+    /// message as it's replayed, so the new log doesn't depend on how fast
+    /// we replay it.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **config**: Flight computer configuration
+    /// - **clock**: Where to get the time from
+    ///
+    pub fn replay(config: &config::Config, clock: Box<dyn clock::Clock>) -> FC {
+
+        let (fc_log_file, log_number) = new_log_file();
+
+        let mut fc = FC::open(config, clock, None, Vec::new(), Box::new(fc_log_file), log_number, 0);
 
         // Write log header
         fc.log_message(&[0, 0, 0, 0], SEQN_NAME, time::Duration::new(0, 0), 4).unwrap();
- 
+
         fc
     }
-}
 
+    /// Start up a flight computer on any transport.
+    ///
+    /// Instead of opening sockets and a log file, use whatever we're given.
+    /// The configured telemetry destinations are ignored. Mostly useful for
+    /// testing with the in-memory transports and a manual clock.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **config**: Flight computer configuration
+    /// - **clock**: Where to get the time from
+    /// - **source**: Where messages come from
+    /// - **telemetry_sinks**: Where to send telemetry
+    /// - **log**: Where to write the log
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{clock, io, log, transport};
+    /// use std::time::Duration;
+    ///
+    /// let clock = clock::ManualClock::new();
+    /// let source = transport::MemorySource::new();
+    /// let sink = transport::MemorySink::new();
+    /// let memory_log = transport::MemoryLog::new();
+    ///
+    /// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock.clone()),
+    ///     Box::new(source.clone()), vec![Box::new(sink.clone())], Box::new(memory_log.clone()));
+    ///
+    /// // A made up message, sent to the ground as soon as we shut down
+    /// flight_computer.telemetry(&[1, 2, 3, 4], *b"TEST", Duration::new(0, 0x0102), 4);
+    /// clock.set(Duration::new(0, 0x0304));
+    /// flight_computer.shutdown(0).unwrap();
+    ///
+    /// assert_eq!(sink.packets(), vec![vec![0, 0, 0, 0,
+    ///     84, 69, 83, 84, 0, 0, 0, 0, 1, 2, 0, 4, 1, 2, 3, 4,
+    ///     83, 72, 68, 78, 0, 0, 0, 0, 3, 4, 0, 4, 0, 0, 0, 0]]);
+    ///
+    /// // The log starts with a header and ends with the shutdown and the
+    /// // sequence number of the next packet
+    /// assert_eq!(memory_log.bytes(), vec![
+    ///     83, 69, 81, 78, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0,
+    ///     83, 72, 68, 78, 0, 0, 0, 0, 3, 4, 0, 4, 0, 0, 0, 0,
+    ///     83, 69, 81, 78, 0, 0, 0, 0, 3, 4, 0, 4, 0, 0, 0, 1]);
+    /// ```
+    pub fn with_transport(config: &config::Config,
+                          clock: Box<dyn clock::Clock>,
+                          source: Box<dyn transport::DatagramSource>,
+                          telemetry_sinks: Vec<Box<dyn transport::TelemetrySink>>,
+                          log: Box<dyn transport::LogSink>) -> FC {
+
+        let mut fc = FC::open(config, clock, Some(source), telemetry_sinks, log, 0, 0);
 
-impl FC {
+        // Write log header
+        fc.log_message(&[0, 0, 0, 0], SEQN_NAME, time::Duration::new(0, 0), 4).unwrap();
+
+        fc
+    }
+
+    // Set up a new flight computer
+    fn open(config: &config::Config,
+            clock: Box<dyn clock::Clock>,
+            source: Option<Box<dyn transport::DatagramSource>>,
+            telemetry_sinks: Vec<Box<dyn transport::TelemetrySink>>,
+            log: Box<dyn transport::LogSink>,
+            log_number: u16,
+            sequence_number: u32) -> FC {
+
+        // Put first sequence number in the telemetry buffer.
+        let mut telemetry_buffer = Vec::with_capacity(P_LIMIT);
+        telemetry_buffer.write_u32::<BigEndian>(sequence_number).unwrap();
+
+        let telemetry_sends = (0..telemetry_sinks.len()).map(|index| SendStats { destination: index as u8, ..Default::default() }).collect();
+        let now = clock.now();
+
+        // Initialise
+        FC {
+            clock,
+            source,
+            telemetry_sinks,
+            log,
+            log_number,
+            log_size: 0,
+            sequence_number,
+            telemetry_buffer,
+            telemetry_records: Vec::new(),
+            telemetry_policies: config.telemetry.policies.clone(),
+            telemetry_counts: HashMap::new(),
+            telemetry_bucket: telemetry::TokenBucket::new(config.telemetry.bandwidth, config.telemetry.burst),
+            telemetry_dropped: 0,
+            telemetry_queued: None,
+            telemetry_max_age: config.telemetry.max_age,
+            raw_packet_bucket: telemetry::TokenBucket::new(RAW_PACKET_RATE, RAW_PACKET_BURST),
+            actuator: None,
+            actuator_sequence_number: 0,
+            receive_buffer: vec![0u8; DATAGRAM_LIMIT],
+            telemetry_sends,
+            actuator_sends: SendStats { destination: ACTUATOR_DESTINATION, ..Default::default() },
+            last_send_report: now,
+        }
+    }
+
+    /// Current time on the flight computer clock (time since boot).
+    pub fn time(&self) -> time::Duration {
+        self.clock.now()
+    }
+
+    /// Which log file we're writing (the `NNN` in `logfile-NNN`).
+    pub fn log_number(&self) -> u16 {
+        self.log_number
+    }
+
+    /// How many bytes of whole records we've written to the log.
+    pub fn log_size(&self) -> u64 {
+        self.log_size
+    }
+
+    /// Current count of telemetry messages sent.
+    pub fn sequence_number(&self) -> u32 {
+        self.sequence_number
+    }
+
+    /// Sequence number of the next packet to the actuator.
+    pub fn actuator_sequence_number(&self) -> u32 {
+        self.actuator_sequence_number
+    }
+
+    /// Count of telemetry messages dropped for being over the bandwidth
+    /// budget.
+    pub fn telemetry_dropped(&self) -> u64 {
+        self.telemetry_dropped
+    }
 
     /// Listen for messages from the network.
     ///
-    /// This makes a blocking `read` call on the `fc_listen_socket`, waiting
-    /// for any message from the outside world. Once received, it will deal
-    /// with the sequence numbers in the header of the data and write the raw
-    /// message to the passed in buffer.
+    /// This waits on every socket we listen on for any message from the
+    /// outside world.
     ///
-    /// #  Returns:
+    /// If there is a partly built telemetry packet waiting to go out, we only
+    /// block until it's due. Call `poll_telemetry` after this returns to send
+    /// it.
     ///
-    /// An Option containing a tuple of data from the socket. The tuple
-    /// contains:
+    /// #  Returns:
     ///
-    /// - **Sequence Number**: Sequence number from the header of the data packet
-    /// - **Port**: Which port the data was sent _from_
-    /// - **Time**: The time that the message was received
-    /// - **Message**: A message buffer that has raw bytes off the wire
+    /// An Option containing the packet, with where it came from and when we
+    /// got it.
     ///
     /// # Example
     ///
@@ -206,36 +613,54 @@ impl FC {
     ///
     /// let mut flight_computer: io::FC = Default::default();
     ///
-    /// if let Some((seqn, recv_port, recv_time, message)) = flight_computer.listen() {
+    /// if let Some(packet) = flight_computer.listen() {
     ///     // Do something here with received data
     /// }
     /// ```
-    pub fn listen(&self) -> Option<(u32, u16, time::Duration, [u8; P_LIMIT - 4])> {
+    pub fn listen(&mut self) -> Option<Packet> {
+        self.listen_until(None)
+    }
 
-        // A buffer to put data in from the port.
-        // Should at least be the size of telemetry message.
-        let mut message_buffer = [0u8; P_LIMIT];
+    /// Listen for messages from the network, but not past a deadline.
+    ///
+    /// The same as `listen`, except that we also give up waiting at the
+    /// deadline, so the caller can do something else that's due.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **deadline**: Time on the flight computer clock to stop waiting at
+    ///
+    pub fn listen_until(&mut self, deadline: Option<time::Duration>) -> Option<Packet> {
+
+        // Don't wait past when the telemetry packet or send statistics are
+        // due
+        let deadline = [deadline, self.telemetry_deadline(), self.next_send_report()].iter().flatten().min().cloned();
+        let timeout = deadline.map(|deadline| {
+            let wait = deadline.checked_sub(self.time()).unwrap_or_default();
+            wait.max(time::Duration::from_millis(1))
+        });
+        let source = match self.source {
+            Some(ref mut source) => source,
+            None => { return None; },
+        };
 
-        // Read from the socket (blocking!)
-        // message_buffer gets filled and we get the number of bytes read
-        // along with and address that the message came from
-        match self.fc_listen_socket.recv_from(&mut message_buffer) {
-            Ok((_, recv_addr)) => {
+        // Wait for something from the source (or the timeout)
+        // receive_buffer gets filled and we get the number of bytes read
+        // along with and address that the message came from. It's big
+        // enough for any datagram, so the packet is always the whole thing.
+        match source.recv(&mut self.receive_buffer, timeout) {
+            Ok(Some((size, recv_addr))) => {
 
                 // Get time for incoming data
-                let recv_time = time::Instant::now().duration_since(self.boot_time);
-
-                // First 4 bytes are the sequence number
-                let mut buf = Cursor::new(&message_buffer[..4]);
-                let seqn = buf.read_u32::<BigEndian>().unwrap();
+                let recv_time = self.time();
 
-                // Rest of the bytes may be part of a message
-                let mut message = [0u8; P_LIMIT - 4];
-                message.clone_from_slice(&message_buffer[4..P_LIMIT]);
-
-                Some((seqn, recv_addr.port(), recv_time, message))
+                Some(Packet {
+                    from: recv_addr,
+                    time: recv_time,
+                    bytes: self.receive_buffer[..size].to_vec(),
+                })
             },
-            Err(_) => { None },  // continue
+            Ok(None) | Err(_) => { None },  // continue
         }
     }
 
@@ -265,11 +690,12 @@ impl FC {
 
         // Header:
         let header = pack_header(name, time, message_size);
-        try!(self.fc_log_file.write(&header));
+        self.log.write(&header)?;
 
         // message:
-        try!(self.fc_log_file.write(&message[0..message_size]));
+        self.log.write(&message[0..message_size])?;
 
+        self.log_size += (HEADER_SIZE + message_size) as u64;
         Ok(())
     }
 
@@ -286,6 +712,11 @@ impl FC {
     /// be send out over the network once will fill the maximum size of a UDP
     /// packet.
     ///
+    /// Not every message makes it: the telemetry policy for the message type
+    /// may turn it off or only send some of them, and if the link is over its
+    /// bandwidth budget low priority messages get dropped when the packet is
+    /// sent.
+    ///
     /// ## Parameters
     ///
     /// - **message**: Byte array containing packed message
@@ -295,83 +726,517 @@ impl FC {
     ///
     pub fn telemetry(&mut self, message: &[u8], name: [u8; 4], time: time::Duration, message_size: usize) {
 
+        // Nowhere to send it (replaying a log)
+        if self.telemetry_sinks.is_empty() {
+            return;
+        }
+
+        let policy = match self.telemetry_policies.get(&name) {
+            Some(policy) => *policy,
+            None => Default::default(),
+        };
+        if !policy.enabled {
+            return;
+        }
+
+        // Decimate, only keep one in every N messages of this type
+        let count = self.telemetry_counts.entry(name).or_insert(0);
+        let keep = count.is_multiple_of(policy.decimation);
+        *count = count.wrapping_add(1);
+        if !keep {
+            return;
+        }
+
         // If we won't have room in the current packet, flush
         if (self.telemetry_buffer.len() + HEADER_SIZE + message_size) > P_LIMIT {
             self.flush_telemetry();
         }
 
+        let start = self.telemetry_buffer.len();
+
         // Header:
         let header = pack_header(name, time, message_size);
         self.telemetry_buffer.extend_from_slice(&header);
 
         // Message:
         self.telemetry_buffer.extend_from_slice(&message[0..message_size]);
-    }
-
-    /// This will actually send the now full and packed telemetry packet,
-    /// and set us up for the next one.
-    fn flush_telemetry(&mut self) {
-
-        // Push out the door
-        let telemetry_addr: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), PSAS_TELEMETRY_UDP_PORT);
 
-        // When did we send this packet
-        let send_time = time::Instant::now().duration_since(self.boot_time);
-
-        self.telemetry_socket.send_to(&self.telemetry_buffer, telemetry_addr).unwrap();
-
-        // Increment SEQN
-        self.sequence_number += 1;
-
-        // Start telemetry buffer over
-        self.telemetry_buffer.clear();
+        self.telemetry_records.push((start, self.telemetry_buffer.len(), policy.priority));
 
-        // Prepend with next sequence number
-        let mut seqn = Vec::with_capacity(4);
-        seqn.write_u32::<BigEndian>(self.sequence_number).unwrap();
-        self.telemetry_buffer.extend_from_slice(&mut seqn);
+        // Start the clock on this packet if it's the first message in it
+        if self.telemetry_queued.is_none() {
+            self.telemetry_queued = Some(self.time());
+        }
+    }
 
-        // Keep track of sequence numbers in the flight computer log too
-        self.log_message(&seqn, SEQN_NAME, send_time, 4).unwrap();
+    /// Send fins somewhere other than the configured actuator address.
+    ///
+    /// Mostly useful for testing with an in-memory sink.
+    pub fn set_actuator(&mut self, sink: Box<dyn transport::TelemetrySink>) {
+        self.actuator = Some(sink);
     }
-}
 
-/// A sequence error message.
-///
-/// When we miss a packet or get an out of order packet we should log that
-/// for future analysis. This stores a error and builds a log-able message.
-///
-/// # Example
-///
-/// ```
-/// use rust_fc::io;
-///
-/// let seqerror = io::SequenceError {
-///     port: 35020,
-///     expected: 12345,
-///     received: 12349,
-/// };
-///
-/// // Now you can log or send the message somewhere
-/// ```
-pub struct SequenceError {
+    /// Send a command to the actuator.
+    ///
+    /// Unlike telemetry this goes out straight away, in a packet of its own:
+    /// a sequence number, then the message with a header. Nothing is sent if
+    /// there's no actuator (like when replaying a log).
+    ///
+    /// ## Parameters
+    ///
+    /// - **message**: Byte array containing packed message
+    /// - **name**: Byte array of the name for this message
+    /// - **time**: Time of message
+    /// - **message_size**: How many bytes to copy from the message array
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{clock, io, transport};
+    /// use std::time::Duration;
+    ///
+    /// let fins = transport::MemorySink::new();
+    /// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock::ManualClock::new()),
+    ///     Box::new(transport::MemorySource::new()), Vec::new(), Box::new(transport::MemoryLog::new()));
+    /// flight_computer.set_actuator(Box::new(fins.clone()));
+    ///
+    /// flight_computer.actuate(&[1, 2], *b"TEST", Duration::new(0, 5), 2);
+    /// assert_eq!(fins.packets(), vec![vec![0, 0, 0, 0, 84, 69, 83, 84, 0, 0, 0, 0, 0, 5, 0, 2, 1, 2]]);
+    /// ```
+    pub fn actuate(&mut self, message: &[u8], name: [u8; 4], time: time::Duration, message_size: usize) {
+        let sink = match self.actuator {
+            Some(ref mut sink) => sink,
+            None => { return; },
+        };
 
-    /// Which port the packet error is from
-    pub port: u16,
+        let mut packet = Vec::with_capacity(4 + HEADER_SIZE + message_size);
+        packet.write_u32::<BigEndian>(self.actuator_sequence_number).unwrap();
+        packet.extend_from_slice(&pack_header(name, time, message_size));
+        packet.extend_from_slice(&message[0..message_size]);
 
-    /// Expected packet sequence number
-    pub expected: u32,
+        // A lost command is replaced by the next one, so don't stop on
+        // errors, but count them so a dead link shows up
+        self.actuator_sends.count(sink.send(&packet));
+        self.actuator_sequence_number = self.actuator_sequence_number.wrapping_add(1);
+    }
 
-    /// Actual received packet sequence number
-    pub received: u32,
-}
+    /// Log a packet we don't recognize.
+    ///
+    /// So that a flood of junk can't fill the disk, these have a budget of
+    /// their own (`RAW_PACKET_RATE`). Anything over it isn't logged.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **packet**: The packet
+    ///
+    /// ## Returns:
+    ///
+    /// True if the packet was logged, false if we were over budget.
+    pub fn log_raw_packet(&mut self, packet: &Packet) -> Result<bool, Error> {
+        let message = RawPacket::from_packet(packet).as_message();
 
+        self.raw_packet_bucket.refill(packet.time);
+        if message.len() > self.raw_packet_bucket.available() {
+            return Ok(false);
+        }
+        self.raw_packet_bucket.spend(message.len());
 
-impl SequenceError {
+        self.log_message(&message, RAW_PACKET_NAME, packet.time, message.len())?;
+        Ok(true)
+    }
 
-    /// Return a copy of this struct as a byte array.
+    /// Record a flight computer event.
     ///
-    /// The PSAS file and message type is always a byte array with big-endian
+    /// Events are things that happen to the flight computer itself (like a
+    /// restart) rather than data from the outside world. They are stamped
+    /// with the current time, logged, and sent to the ground.
+    ///
+    /// ## Parameters
+    ///
+    /// - **message**: Byte array containing packed message
+    /// - **name**: Byte array of the name for this message
+    /// - **message_size**: How many bytes to copy from the message array
+    ///
+    pub fn event(&mut self, message: &[u8], name: [u8; 4], message_size: usize) -> Result<(), Error> {
+        let now = self.time();
+        self.log_message(message, name, now, message_size)?;
+        self.telemetry(message, name, now, message_size);
+        Ok(())
+    }
+
+    /// Send a partly built telemetry packet if it's been waiting too long.
+    ///
+    /// When data is coming in slowly (on the pad, or if a sensor goes quiet)
+    /// it could take a long time to fill a packet. This makes sure nothing
+    /// waits in the telemetry buffer for longer than the configured maximum
+    /// age. Call it every time around the main loop.
+    pub fn poll_telemetry(&mut self) {
+        if let Some(deadline) = self.telemetry_deadline() {
+            if self.time() >= deadline {
+                self.flush_telemetry();
+            }
+        }
+    }
+
+    /// When send statistics are next due, if there's anywhere we send to.
+    pub fn next_send_report(&self) -> Option<time::Duration> {
+        if self.telemetry_sinks.is_empty() && self.actuator.is_none() {
+            return None;
+        }
+        Some(self.last_send_report + time::Duration::from_nanos(SEND_STATS_INTERVAL))
+    }
+
+    /// Log and send statistics on how sending to each destination went, if
+    /// it's time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{clock, io, log, transport};
+    /// use std::io::{Error, ErrorKind};
+    /// use std::time::Duration;
+    ///
+    /// // Somewhere nothing gets through to
+    /// struct Dead;
+    /// impl transport::TelemetrySink for Dead {
+    ///     fn send(&mut self, _: &[u8]) -> Result<(), Error> {
+    ///         Err(Error::new(ErrorKind::ConnectionRefused, "nobody home"))
+    ///     }
+    /// }
+    ///
+    /// let clock = clock::ManualClock::new();
+    /// let memory_log = transport::MemoryLog::new();
+    /// let mut flight_computer = io::FC::with_transport(&Default::default(), Box::new(clock.clone()),
+    ///     Box::new(transport::MemorySource::new()), vec![Box::new(transport::MemorySink::new())], Box::new(memory_log.clone()));
+    /// flight_computer.set_actuator(Box::new(Dead));
+    ///
+    /// flight_computer.actuate(&[1, 2], *b"TEST", Duration::new(0, 0), 2);
+    /// flight_computer.actuate(&[1, 2], *b"TEST", Duration::new(0, 0), 2);
+    /// clock.set(Duration::from_secs(1));
+    /// flight_computer.report_sends();
+    ///
+    /// let bytes = memory_log.bytes();
+    /// let sends: Vec<io::SendStats> = log::Reader::new(&bytes[..]).filter_map(|r| match r.unwrap().message {
+    ///     log::Message::SendStats(stats) => Some(stats),
+    ///     _ => None,
+    /// }).collect();
+    /// assert_eq!(sends[0], io::SendStats { destination: 0, packets: 0, errors: 0 });
+    /// assert_eq!(sends[1], io::SendStats { destination: io::ACTUATOR_DESTINATION, packets: 0, errors: 2 });
+    /// ```
+    pub fn report_sends(&mut self) {
+        let now = self.time();
+        match self.next_send_report() {
+            Some(due) if now >= due => { },
+            _ => { return; },
+        }
+        self.last_send_report = now;
+
+        let mut stats: Vec<SendStats> = self.telemetry_sends.clone();
+        if self.actuator.is_some() {
+            stats.push(self.actuator_sends);
+        }
+        for sends in self.telemetry_sends.iter_mut().chain(Some(&mut self.actuator_sends)) {
+            *sends = SendStats { destination: sends.destination, ..Default::default() };
+        }
+
+        for sends in stats {
+            self.log_message(&sends.as_message(), SEND_NAME, now, SIZE_OF_SEND).unwrap();
+            self.telemetry(&sends.as_message(), SEND_NAME, now, SIZE_OF_SEND);
+        }
+    }
+
+    // When the current telemetry packet has to go out, if there is one
+    fn telemetry_deadline(&self) -> Option<time::Duration> {
+        if self.telemetry_max_age == time::Duration::new(0, 0) {
+            return None;
+        }
+        self.telemetry_queued.map(|queued| queued + self.telemetry_max_age)
+    }
+
+    /// Shut down cleanly.
+    ///
+    /// Records a shutdown event, sends out whatever is waiting in the
+    /// telemetry buffer and then makes sure the log file actually made it to
+    /// disk.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **signal**: The signal that asked us to stop
+    ///
+    pub fn shutdown(&mut self, signal: i32) -> Result<(), Error> {
+
+        let message = shutdown::Shutdown { signal: signal as u32 };
+        self.event(&message.as_message(), shutdown::SHUTDOWN_NAME, shutdown::SIZE_OF_SHUTDOWN)?;
+
+        // Don't leave a partly built packet behind
+        if self.telemetry_queued.is_some() {
+            self.flush_telemetry();
+        }
+
+        self.log.sync()
+    }
+
+    /// This will actually send the now full and packed telemetry packet,
+    /// and set us up for the next one.
+    fn flush_telemetry(&mut self) {
+
+        // When did we send this packet
+        let send_time = self.time();
+
+        // If this packet would put us over the bandwidth budget, drop the
+        // least important messages until it fits
+        self.telemetry_bucket.refill(send_time);
+        let budget = self.telemetry_bucket.available();
+        if self.telemetry_buffer.len() > budget {
+            self.drop_telemetry(budget);
+        }
+
+        // Nothing left to send
+        if self.telemetry_records.is_empty() {
+            self.telemetry_buffer.truncate(4);
+            self.telemetry_queued = None;
+            return;
+        }
+
+        self.telemetry_bucket.spend(self.telemetry_buffer.len());
+
+        // Push out the door. If one destination is unreachable we still want
+        // the packet to get to the others, so don't stop on errors, but count
+        // them for each destination.
+        for (sink, sends) in self.telemetry_sinks.iter_mut().zip(&mut self.telemetry_sends) {
+            sends.count(sink.send(&self.telemetry_buffer));
+        }
+
+        // Increment SEQN
+        self.sequence_number += 1;
+
+        // Start telemetry buffer over
+        self.telemetry_buffer.clear();
+        self.telemetry_records.clear();
+        self.telemetry_queued = None;
+
+        // Prepend with next sequence number
+        let mut seqn = Vec::with_capacity(4);
+        seqn.write_u32::<BigEndian>(self.sequence_number).unwrap();
+        self.telemetry_buffer.extend_from_slice(&seqn);
+
+        // Keep track of sequence numbers in the flight computer log too
+        self.log_message(&seqn, SEQN_NAME, send_time, 4).unwrap();
+    }
+
+    // Drop messages out of the telemetry buffer, least important (and then
+    // oldest) first, until it fits in the budget. Critical messages stay no
+    // matter what.
+    fn drop_telemetry(&mut self, budget: usize) {
+
+        let mut order: Vec<usize> = (0..self.telemetry_records.len()).collect();
+        order.sort_by_key(|&i| self.telemetry_records[i].2);
+
+        let mut size = self.telemetry_buffer.len();
+        let mut keep = vec![true; self.telemetry_records.len()];
+        for i in order {
+            let (start, end, priority) = self.telemetry_records[i];
+            if size <= budget || priority == telemetry::Priority::Critical {
+                break;
+            }
+            keep[i] = false;
+            size -= end - start;
+            self.telemetry_dropped += 1;
+        }
+
+        // Rebuild the packet out of what's left
+        let mut buffer = Vec::with_capacity(P_LIMIT);
+        let mut records = Vec::new();
+        buffer.extend_from_slice(&self.telemetry_buffer[..4]);
+        for (i, &(start, end, priority)) in self.telemetry_records.iter().enumerate() {
+            if keep[i] {
+                records.push((buffer.len(), buffer.len() + end - start, priority));
+                buffer.extend_from_slice(&self.telemetry_buffer[start..end]);
+            }
+        }
+        self.telemetry_buffer = buffer;
+        self.telemetry_records = records;
+    }
+}
+
+/// A packet from the network.
+pub struct Packet {
+
+    /// Where the packet came from
+    pub from: SocketAddr,
+
+    /// When we got it
+    pub time: time::Duration,
+
+    /// The whole packet, as raw bytes off the wire
+    pub bytes: Vec<u8>,
+}
+
+
+impl Packet {
+
+    /// The sequence number at the start of the packet, if it's long enough
+    /// to have one.
+    pub fn seqn(&self) -> Option<u32> {
+        if self.bytes.len() < 4 {
+            return None;
+        }
+        Some(Cursor::new(&self.bytes[..4]).read_u32::<BigEndian>().unwrap())
+    }
+
+    /// Everything after the sequence number.
+    pub fn message(&self) -> &[u8] {
+        if self.bytes.len() < 4 {
+            return &[];
+        }
+        &self.bytes[4..]
+    }
+}
+
+
+/// A packet we didn't recognize.
+///
+/// We keep where it came from, how long it was and the start of it, which
+/// should be enough to work out what sent it.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::io;
+///
+/// let packet = io::Packet {
+///     from: "192.168.1.30:35099".parse().unwrap(),
+///     time: Default::default(),
+///     bytes: vec![0xff; 100],
+/// };
+///
+/// let raw = io::RawPacket::from_packet(&packet);
+/// assert_eq!(raw.length, 100);
+/// assert_eq!(raw.payload.len(), io::RAW_PACKET_PAYLOAD_LIMIT);
+///
+/// let message = raw.as_message();
+/// assert_eq!(io::RawPacket::from_message(&message).from, packet.from);
+/// ```
+pub struct RawPacket {
+
+    /// Where the packet came from
+    pub from: SocketAddr,
+
+    /// How long the packet was. We receive whole datagrams, so this is the
+    /// real length (up to 65535 bytes, which is as big as they get).
+    pub length: u16,
+
+    /// The start of the packet (at most `RAW_PACKET_PAYLOAD_LIMIT` bytes)
+    pub payload: Vec<u8>,
+}
+
+
+impl RawPacket {
+
+    /// Keep the interesting parts of a packet.
+    pub fn from_packet(packet: &Packet) -> RawPacket {
+        let keep = packet.bytes.len().min(RAW_PACKET_PAYLOAD_LIMIT);
+        RawPacket {
+            from: packet.from,
+            length: packet.bytes.len().min(u16::MAX as usize) as u16,
+            payload: packet.bytes[..keep].to_vec(),
+        }
+    }
+
+    /// Read a raw packet back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> RawPacket {
+        let mut message = Cursor::new(message_buffer);
+
+        let mut address = [0u8; 16];
+        address.copy_from_slice(&message_buffer[..16]);
+        message.set_position(16);
+        let address = Ipv6Addr::from(address);
+        let ip = match address.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(address),
+        };
+
+        RawPacket {
+            from:    SocketAddr::new(ip, message.read_u16::<BigEndian>().unwrap()),
+            length:  message.read_u16::<BigEndian>().unwrap(),
+            payload: message_buffer[SIZE_OF_RAW_PACKET..].to_vec(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The address is always stored as 16 bytes, with IPv4 addresses mapped
+    /// into IPv6 (`::ffff:a.b.c.d`). The message is `SIZE_OF_RAW_PACKET`
+    /// bytes plus the payload.
+    pub fn as_message(&self) -> Vec<u8> {
+        let address = match self.from.ip() {
+            IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+            IpAddr::V6(v6) => v6,
+        };
+
+        let mut message = Vec::with_capacity(SIZE_OF_RAW_PACKET + self.payload.len());
+
+        // Struct Fields:
+        message.write_all(&address.octets()).unwrap();
+        message.write_u16::<BigEndian>(self.from.port()).unwrap();
+        message.write_u16::<BigEndian>(self.length).unwrap();
+        message.write_all(&self.payload).unwrap();
+        message
+    }
+}
+
+
+/// A sequence error message.
+///
+/// When we miss a packet or get an out of order packet we should log that
+/// for future analysis. This stores a error and builds a log-able message.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::io;
+///
+/// let seqerror = io::SequenceError {
+///     port: 35020,
+///     expected: 12345,
+///     received: 12349,
+/// };
+///
+/// // Now you can log or send the message somewhere
+/// ```
+pub struct SequenceError {
+
+    /// Which port the packet error is from
+    pub port: u16,
+
+    /// Expected packet sequence number
+    pub expected: u32,
+
+    /// Actual received packet sequence number
+    pub received: u32,
+}
+
+
+impl SequenceError {
+
+    /// Read a sequence error back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> SequenceError {
+        let mut message = Cursor::new(message_buffer);
+
+        SequenceError {
+            port:     message.read_u16::<BigEndian>().unwrap(),
+            expected: message.read_u32::<BigEndian>().unwrap(),
+            received: message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
     /// representation of fields in a struct.
     pub fn as_message(&self) -> [u8; SIZE_OF_SEQE] {
         let mut buffer = [0u8; SIZE_OF_SEQE];
@@ -386,3 +1251,770 @@ impl SequenceError {
         buffer
     }
 }
+
+
+/// How a packet's sequence number compares to what we expected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sequence {
+
+    /// The one we expected next (or the first from this source)
+    InOrder,
+
+    /// Newer than we expected, we missed this many packets in between
+    Gap(u32),
+
+    /// One we've already had
+    Duplicate,
+
+    /// One we skipped over as missing that turned up late
+    Reordered,
+
+    /// Way out of line. The sender must have restarted and started counting
+    /// over, so we follow the new sequence numbers from here.
+    SenderRestart,
+}
+
+
+impl Sequence {
+
+    /// True if this is new data we should use. Duplicates and packets that
+    /// turn up late are stale.
+    pub fn is_new(&self) -> bool {
+        !matches!(*self, Sequence::Duplicate | Sequence::Reordered)
+    }
+}
+
+
+/// Sequence statistics message size (bytes)
+pub const SIZE_OF_SEQS: usize = 22;
+
+/// Sequence statistics message name (ASCII: SEQS)
+pub const SEQS_NAME: [u8;4] = [83, 69, 81, 83];
+
+/// How often to log and send sequence statistics (nanoseconds)
+pub const SEQUENCE_STATS_INTERVAL: u64 = 1000000000;
+
+/// How many packets back we remember, to tell duplicates from late packets.
+/// Anything older than this is taken as the sender restarting.
+pub const SEQUENCE_WINDOW: u32 = 64;
+
+/// The biggest jump forward we count as a gap. Anything bigger is taken as
+/// the sender restarting.
+pub const SEQUENCE_MAX_GAP: u32 = 65536;
+
+/// How many stale packets in a row it takes for us to decide the sender
+/// restarted (in case it restarted just behind where it was).
+pub const SEQUENCE_STALE_RUN: u32 = 8;
+
+
+/// Running sequence statistics for a single source.
+///
+/// Counts are totals since we first heard from the source.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SequenceStats {
+
+    /// Which port the packets are from
+    pub port: u16,
+
+    /// Packets received
+    pub received: u32,
+
+    /// Packets we never got
+    pub lost: u32,
+
+    /// Packets we got more than once
+    pub duplicates: u32,
+
+    /// Packets that arrived after newer ones
+    pub reordered: u32,
+
+    /// Times the sender restarted its sequence numbers
+    pub restarts: u32,
+}
+
+
+impl SequenceStats {
+
+    /// Read sequence statistics back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> SequenceStats {
+        let mut message = Cursor::new(message_buffer);
+
+        SequenceStats {
+            port:       message.read_u16::<BigEndian>().unwrap(),
+            received:   message.read_u32::<BigEndian>().unwrap(),
+            lost:       message.read_u32::<BigEndian>().unwrap(),
+            duplicates: message.read_u32::<BigEndian>().unwrap(),
+            reordered:  message.read_u32::<BigEndian>().unwrap(),
+            restarts:   message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_SEQS] {
+        let mut buffer = [0u8; SIZE_OF_SEQS];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u16::<BigEndian>(self.port).unwrap();
+            message.write_u32::<BigEndian>(self.received).unwrap();
+            message.write_u32::<BigEndian>(self.lost).unwrap();
+            message.write_u32::<BigEndian>(self.duplicates).unwrap();
+            message.write_u32::<BigEndian>(self.reordered).unwrap();
+            message.write_u32::<BigEndian>(self.restarts).unwrap();
+        }
+        buffer
+    }
+}
+
+
+// Where we're up to with a single source
+struct Stream {
+
+    // Sequence number we expect next
+    expected: u32,
+
+    // Which of the packets before `expected` we've had. Bit 0 is
+    // `expected - 1`, bit 1 is `expected - 2` and so on.
+    history: u64,
+
+    // Which of the packets before `expected` we skipped over and counted as
+    // lost, numbered the same way as `history`
+    missing: u64,
+
+    // How many stale packets we've had in a row
+    stale_run: u32,
+
+    // Statistics
+    stats: SequenceStats,
+}
+
+
+/// Keeps track of sequence numbers for each source of packets.
+///
+/// Sequence numbers are compared with wrapping arithmetic, so counting past
+/// `u32::MAX` and back to zero is just the next packet.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::io::{self, Sequence};
+///
+/// let mut tracker: io::SequenceTracker = Default::default();
+///
+/// assert_eq!(tracker.check(35020, 10).0, Sequence::InOrder);
+/// assert_eq!(tracker.check(35020, 13).0, Sequence::Gap(2));
+/// assert_eq!(tracker.check(35020, 12).0, Sequence::Reordered);
+/// assert_eq!(tracker.check(35020, 12).0, Sequence::Duplicate);
+///
+/// // Every packet that isn't in order comes with a sequence error to log
+/// let (_, error) = tracker.check(35020, 5000000);
+/// let error = error.unwrap();
+/// assert_eq!((error.expected, error.received), (14, 5000000));
+///
+/// let stats = tracker.stats(35020).unwrap();
+/// assert_eq!((stats.received, stats.lost, stats.restarts), (5, 1, 1));
+///
+/// // A late packet from before the first one we heard was never lost
+/// tracker.check(35030, 100);
+/// assert_eq!(tracker.check(35030, 99).0, Sequence::Reordered);
+/// assert_eq!(tracker.stats(35030).unwrap().lost, 0);
+/// ```
+pub struct SequenceTracker {
+
+    /// Each source we've heard from, by port
+    streams: HashMap<u16, Stream>,
+
+    /// How often to report statistics
+    interval: time::Duration,
+
+    /// When we last reported statistics
+    last_report: time::Duration,
+}
+
+
+impl Default for SequenceTracker {
+    fn default () -> SequenceTracker {
+        SequenceTracker::new(time::Duration::from_nanos(SEQUENCE_STATS_INTERVAL))
+    }
+}
+
+
+impl SequenceTracker {
+
+    /// Start tracking with nothing heard from anyone yet.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **interval**: How often `report` gives back statistics
+    ///
+    pub fn new(interval: time::Duration) -> SequenceTracker {
+        SequenceTracker {
+            streams: HashMap::new(),
+            interval,
+            last_report: time::Duration::new(0, 0),
+        }
+    }
+
+    /// Pick up a source where we left off (after a crash).
+    ///
+    /// ## Parameters:
+    ///
+    /// - **port**: Which port the packets are from
+    /// - **expected**: The sequence number we expect next
+    ///
+    pub fn resume(&mut self, port: u16, expected: u32) {
+        self.streams.insert(port, Stream {
+            expected,
+            history: 0,
+            missing: 0,
+            stale_run: 0,
+            stats: SequenceStats { port, ..Default::default() },
+        });
+    }
+
+    /// The sequence number we expect next from a source, if we've heard
+    /// from it.
+    pub fn expected(&self, port: u16) -> Option<u32> {
+        self.streams.get(&port).map(|stream| stream.expected)
+    }
+
+    /// The sequence number we expect next from each source we've heard
+    /// from, in order of port.
+    pub fn expected_by_port(&self) -> Vec<(u16, u32)> {
+        let mut expected: Vec<(u16, u32)> = self.streams.iter().map(|(&port, stream)| (port, stream.expected)).collect();
+        expected.sort();
+        expected
+    }
+
+    /// Statistics for a source, if we've heard from it.
+    pub fn stats(&self, port: u16) -> Option<SequenceStats> {
+        self.streams.get(&port).map(|stream| stream.stats)
+    }
+
+    /// Check the sequence number of a packet.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **port**: Which port the packet is from
+    /// - **seqn**: Sequence number from the header of the packet
+    ///
+    /// ## Returns:
+    ///
+    /// How the packet fits in the sequence, and a sequence error to log if
+    /// it isn't the one we expected.
+    pub fn check(&mut self, port: u16, seqn: u32) -> (Sequence, Option<SequenceError>) {
+
+        // The first packet from anywhere is always in order
+        let stream = self.streams.entry(port).or_insert_with(|| Stream {
+            expected: seqn,
+            history: 0,
+            missing: 0,
+            stale_run: 0,
+            stats: SequenceStats { port, ..Default::default() },
+        });
+
+        let expected = stream.expected;
+        let ahead = seqn.wrapping_sub(expected);
+        let behind = expected.wrapping_sub(seqn);
+        stream.stats.received = stream.stats.received.wrapping_add(1);
+
+        let sequence = if ahead == 0 {
+            Sequence::InOrder
+        }
+        else if ahead <= SEQUENCE_MAX_GAP {
+            Sequence::Gap(ahead)
+        }
+        else if behind <= SEQUENCE_WINDOW && stream.stale_run + 1 < SEQUENCE_STALE_RUN {
+            if stream.history & (1 << (behind - 1)) != 0 {
+                Sequence::Duplicate
+            }
+            else {
+                Sequence::Reordered
+            }
+        }
+        else {
+            Sequence::SenderRestart
+        };
+
+        match sequence {
+            Sequence::InOrder | Sequence::Gap(_) => {
+                let step = ahead + 1;
+                stream.history = if step < 64 { stream.history << step } else { 0 };
+                stream.history |= 1;
+                stream.missing = if step < 64 { stream.missing << step } else { 0 };
+                stream.expected = seqn.wrapping_add(1);
+                stream.stale_run = 0;
+                if let Sequence::Gap(missed) = sequence {
+                    stream.stats.lost = stream.stats.lost.wrapping_add(missed);
+                    let skipped: u64 = if missed >= 63 { !0 } else { (1 << missed) - 1 };
+                    stream.missing |= skipped << 1;
+                }
+            },
+            Sequence::Duplicate => {
+                stream.stale_run += 1;
+                stream.stats.duplicates = stream.stats.duplicates.wrapping_add(1);
+            },
+            Sequence::Reordered => {
+                // If we counted it as lost when we skipped over it, it isn't
+                // any more. One from before the first packet we heard (or
+                // before a restart) was never counted.
+                let bit = 1 << (behind - 1);
+                stream.history |= bit;
+                stream.stale_run += 1;
+                stream.stats.reordered = stream.stats.reordered.wrapping_add(1);
+                if stream.missing & bit != 0 {
+                    stream.missing &= !bit;
+                    stream.stats.lost = stream.stats.lost.saturating_sub(1);
+                }
+            },
+            Sequence::SenderRestart => {
+                stream.history = 1;
+                stream.missing = 0;
+                stream.expected = seqn.wrapping_add(1);
+                stream.stale_run = 0;
+                stream.stats.restarts = stream.stats.restarts.wrapping_add(1);
+            },
+        }
+
+        let error = match sequence {
+            Sequence::InOrder => None,
+            _ => Some(SequenceError { port, expected, received: seqn }),
+        };
+
+        (sequence, error)
+    }
+
+    /// Start the first reporting period. Until this is called it starts at
+    /// zero, which after a restart would make the first report cover the
+    /// whole flight so far.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock
+    ///
+    pub fn start(&mut self, now: time::Duration) {
+        self.last_report = now;
+    }
+
+    /// When the next report is due.
+    pub fn next_report(&self) -> time::Duration {
+        self.last_report + self.interval
+    }
+
+    /// Statistics for every source, if it's time for another report.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock
+    ///
+    /// ## Returns:
+    ///
+    /// Statistics for each source (in order of port), or nothing if we
+    /// reported less than an interval ago.
+    pub fn report(&mut self, now: time::Duration) -> Vec<SequenceStats> {
+        if now < self.last_report + self.interval {
+            return Vec::new();
+        }
+        self.last_report = now;
+
+        let mut stats: Vec<SequenceStats> = self.streams.values().map(|stream| stream.stats).collect();
+        stats.sort_by_key(|stats| stats.port);
+        stats
+    }
+}
+
+
+/// Link statistics message size (bytes)
+pub const SIZE_OF_LINK: usize = 46;
+
+/// Link statistics message name (ASCII: LINK)
+pub const LINK_NAME: [u8;4] = [76, 73, 78, 75];
+
+/// How often to log and send link statistics (nanoseconds)
+pub const LINK_STATS_INTERVAL: u64 = 1000000000;
+
+
+/// How a single source's link did over one reporting period.
+///
+/// The spread between the shortest and longest time between packets is how
+/// much jitter there is on the link.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct LinkStats {
+
+    /// Which port the packets are from
+    pub port: u16,
+
+    /// Packets received
+    pub packets: u32,
+
+    /// Packets received per second [Hz]
+    pub rate: f64,
+
+    /// Number of gaps in the sequence numbers
+    pub gaps: u32,
+
+    /// Packets missing in those gaps
+    pub lost: u32,
+
+    /// Packets we got more than once
+    pub duplicates: u32,
+
+    /// Packets we didn't recognize
+    pub unknown: u32,
+
+    /// Packets we didn't recognize and didn't log either, because we were
+    /// over the budget for logging them
+    pub unlogged: u32,
+
+    /// Shortest time between packets [µs]
+    pub interval_min: u32,
+
+    /// Average time between packets [µs]
+    pub interval_mean: u32,
+
+    /// Longest time between packets [µs]
+    pub interval_max: u32,
+}
+
+
+impl LinkStats {
+
+    /// Read link statistics back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> LinkStats {
+        let mut message = Cursor::new(message_buffer);
+
+        LinkStats {
+            port:          message.read_u16::<BigEndian>().unwrap(),
+            packets:       message.read_u32::<BigEndian>().unwrap(),
+            rate:          message.read_f64::<BigEndian>().unwrap(),
+            gaps:          message.read_u32::<BigEndian>().unwrap(),
+            lost:          message.read_u32::<BigEndian>().unwrap(),
+            duplicates:    message.read_u32::<BigEndian>().unwrap(),
+            unknown:       message.read_u32::<BigEndian>().unwrap(),
+            unlogged:      message.read_u32::<BigEndian>().unwrap(),
+            interval_min:  message.read_u32::<BigEndian>().unwrap(),
+            interval_mean: message.read_u32::<BigEndian>().unwrap(),
+            interval_max:  message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_LINK] {
+        let mut buffer = [0u8; SIZE_OF_LINK];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u16::<BigEndian>(self.port).unwrap();
+            message.write_u32::<BigEndian>(self.packets).unwrap();
+            message.write_f64::<BigEndian>(self.rate).unwrap();
+            message.write_u32::<BigEndian>(self.gaps).unwrap();
+            message.write_u32::<BigEndian>(self.lost).unwrap();
+            message.write_u32::<BigEndian>(self.duplicates).unwrap();
+            message.write_u32::<BigEndian>(self.unknown).unwrap();
+            message.write_u32::<BigEndian>(self.unlogged).unwrap();
+            message.write_u32::<BigEndian>(self.interval_min).unwrap();
+            message.write_u32::<BigEndian>(self.interval_mean).unwrap();
+            message.write_u32::<BigEndian>(self.interval_max).unwrap();
+        }
+        buffer
+    }
+}
+
+
+// What we've seen from a single source this reporting period
+#[derive(Default)]
+struct Link {
+
+    // When the last packet arrived (this period or before)
+    last_arrival: Option<time::Duration>,
+
+    // Counts
+    packets: u32,
+    gaps: u32,
+    lost: u32,
+    duplicates: u32,
+    unknown: u32,
+    unlogged: u32,
+
+    // Time between packets
+    interval_min: Option<time::Duration>,
+    interval_max: time::Duration,
+    interval_total: time::Duration,
+    intervals: u32,
+}
+
+
+/// Whole microseconds in a duration, for statistics messages. Saturates
+/// rather than wrapping if the duration is too long.
+pub fn micros(duration: time::Duration) -> u32 {
+    let micros = duration.as_secs() * 1000000 + duration.subsec_micros() as u64;
+    micros.min(u32::MAX as u64) as u32
+}
+
+
+/// Watches how packets arrive from each source.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::io::{self, Sequence};
+/// use std::time::Duration;
+///
+/// let mut links: io::LinkMonitor = Default::default();
+///
+/// links.record(35020, Duration::from_millis(100), Sequence::InOrder);
+/// links.record(35020, Duration::from_millis(110), Sequence::Gap(3));
+/// links.record(35020, Duration::from_millis(130), Sequence::InOrder);
+///
+/// let stats = links.report(Duration::from_secs(1));
+/// assert_eq!(stats[0].packets, 3);
+/// assert_eq!(stats[0].rate, 3.0);
+/// assert_eq!((stats[0].gaps, stats[0].lost), (1, 3));
+/// assert_eq!((stats[0].interval_min, stats[0].interval_mean, stats[0].interval_max), (10000, 15000, 20000));
+///
+/// // Next time round nothing has arrived
+/// let stats = links.report(Duration::from_secs(2));
+/// assert_eq!(stats[0].packets, 0);
+/// ```
+pub struct LinkMonitor {
+
+    /// Each source we've heard from, by port
+    links: HashMap<u16, Link>,
+
+    /// How often to report statistics
+    interval: time::Duration,
+
+    /// When we last reported statistics (the start of this period)
+    last_report: time::Duration,
+}
+
+
+impl Default for LinkMonitor {
+    fn default () -> LinkMonitor {
+        LinkMonitor::new(time::Duration::from_nanos(LINK_STATS_INTERVAL))
+    }
+}
+
+
+impl LinkMonitor {
+
+    /// Start watching with nothing heard from anyone yet.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **interval**: How often `report` gives back statistics
+    ///
+    pub fn new(interval: time::Duration) -> LinkMonitor {
+        LinkMonitor {
+            links: HashMap::new(),
+            interval,
+            last_report: time::Duration::new(0, 0),
+        }
+    }
+
+    /// Note that a packet arrived.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **port**: Which port the packet is from
+    /// - **time**: When it arrived
+    /// - **sequence**: How it fit in the sequence (from a `SequenceTracker`)
+    ///
+    pub fn record(&mut self, port: u16, time: time::Duration, sequence: Sequence) {
+        let link = self.arrival(port, time);
+        match sequence {
+            Sequence::Gap(missed) => {
+                link.gaps = link.gaps.wrapping_add(1);
+                link.lost = link.lost.wrapping_add(missed);
+            },
+            Sequence::Duplicate => { link.duplicates = link.duplicates.wrapping_add(1); },
+            _ => { },
+        }
+    }
+
+    /// Note that a packet we didn't recognize arrived.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **port**: Which port the packet is from
+    /// - **time**: When it arrived
+    /// - **logged**: Whether we logged it
+    ///
+    pub fn record_unknown(&mut self, port: u16, time: time::Duration, logged: bool) {
+        let link = self.arrival(port, time);
+        link.unknown = link.unknown.wrapping_add(1);
+        if !logged {
+            link.unlogged = link.unlogged.wrapping_add(1);
+        }
+    }
+
+    // Count a packet and the time since the last one
+    fn arrival(&mut self, port: u16, time: time::Duration) -> &mut Link {
+        let link = self.links.entry(port).or_default();
+
+        link.packets = link.packets.wrapping_add(1);
+
+        if let Some(last_arrival) = link.last_arrival {
+            let interval = time.checked_sub(last_arrival).unwrap_or_default();
+            if link.interval_min.map(|min| interval < min).unwrap_or(true) {
+                link.interval_min = Some(interval);
+            }
+            if interval > link.interval_max {
+                link.interval_max = interval;
+            }
+            link.interval_total += interval;
+            link.intervals += 1;
+        }
+        link.last_arrival = Some(time);
+        link
+    }
+
+    /// Start the first reporting period. Until this is called it starts at
+    /// zero, which after a restart would make the first report cover the
+    /// whole flight so far.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock
+    ///
+    pub fn start(&mut self, now: time::Duration) {
+        self.last_report = now;
+    }
+
+    /// When the next report is due.
+    pub fn next_report(&self) -> time::Duration {
+        self.last_report + self.interval
+    }
+
+    /// Statistics for every source since the last report, if it's time for
+    /// another one.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **now**: Current time on the flight computer clock
+    ///
+    /// ## Returns:
+    ///
+    /// Statistics for each source we've ever heard from (in order of port),
+    /// or nothing if we reported less than an interval ago. A source that has
+    /// gone quiet shows up with no packets.
+    pub fn report(&mut self, now: time::Duration) -> Vec<LinkStats> {
+        if now < self.last_report + self.interval {
+            return Vec::new();
+        }
+        let period = now - self.last_report;
+        self.last_report = now;
+
+        let mut stats: Vec<LinkStats> = self.links.iter_mut().map(|(&port, link)| {
+            let mean = if link.intervals > 0 { link.interval_total / link.intervals } else { time::Duration::new(0, 0) };
+            let stats = LinkStats {
+                port,
+                packets: link.packets,
+                rate: link.packets as f64 / (period.as_secs() as f64 + period.subsec_nanos() as f64 / 1e9),
+                gaps: link.gaps,
+                lost: link.lost,
+                duplicates: link.duplicates,
+                unknown: link.unknown,
+                unlogged: link.unlogged,
+                interval_min: micros(link.interval_min.unwrap_or_default()),
+                interval_mean: micros(mean),
+                interval_max: micros(link.interval_max),
+            };
+
+            // Start the next period, but remember when the last packet came
+            // in so we get the time between periods right
+            *link = Link { last_arrival: link.last_arrival, ..Default::default() };
+            stats
+        }).collect();
+        stats.sort_by_key(|stats| stats.port);
+        stats
+    }
+}
+
+
+/// Send statistics message size (bytes)
+pub const SIZE_OF_SEND: usize = 9;
+
+/// Send statistics message name (ASCII: SEND)
+pub const SEND_NAME: [u8;4] = [83, 69, 78, 68];
+
+/// How often to log and send send statistics (nanoseconds)
+pub const SEND_STATS_INTERVAL: u64 = 1000000000;
+
+/// Destination number of the actuator in send statistics
+pub const ACTUATOR_DESTINATION: u8 = 255;
+
+
+/// How sending to one destination went over one reporting period (`SEND`
+/// message).
+///
+/// Sends can fail without stopping the flight computer (a ground station
+/// that isn't up yet, or a dead link to the actuator), so this is how we find
+/// out that they are.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SendStats {
+
+    /// Which destination: telemetry destinations are numbered from 0 in the
+    /// order they're configured, and the actuator is `ACTUATOR_DESTINATION`
+    pub destination: u8,
+
+    /// Packets sent
+    pub packets: u32,
+
+    /// Packets we failed to send
+    pub errors: u32,
+}
+
+
+impl SendStats {
+
+    // Count a packet, and whether it went
+    fn count(&mut self, result: Result<(), Error>) {
+        match result {
+            Ok(_) => { self.packets = self.packets.wrapping_add(1); },
+            Err(_) => { self.errors = self.errors.wrapping_add(1); },
+        }
+    }
+
+    /// Read send statistics back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> SendStats {
+        let mut message = Cursor::new(message_buffer);
+
+        SendStats {
+            destination: message.read_u8().unwrap(),
+            packets:     message.read_u32::<BigEndian>().unwrap(),
+            errors:      message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_SEND] {
+        let mut buffer = [0u8; SIZE_OF_SEND];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u8(self.destination).unwrap();
+            message.write_u32::<BigEndian>(self.packets).unwrap();
+            message.write_u32::<BigEndian>(self.errors).unwrap();
+        }
+        buffer
+    }
+}
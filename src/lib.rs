@@ -80,8 +80,8 @@ functionality here, so instead I'll try for a minimum viable product that:
  - Send telemetry back over a network
  - Send some kind of 'control' message based on current state
 
-Specifically there is no event loop and no commands to interpret, so it should
-be much simpler to implement.
+Specifically there are no commands to interpret, and the event loop is wired up
+in code rather than generated, so it should be much simpler to implement.
 
 
 ## The Rust Implementation
@@ -91,18 +91,44 @@ There are a few modules for keeping similar code together:
  - A **devices** module that will know how to read IMU data from an array of bytes
  - An **io** module that will keep track of all sockets and file handlers. This is the main interface for reading and writing data
  - A **state** module that will track state
+ - A **log** module that reads back the log files we write
+ - A **report** module that summarizes a flight from its log
  - And a **control** module that compute a control signal
+ - An **actuator** module that turns the control signal into commands the fins can follow
+ - A **clock** module so time can come from the system or be set by hand (for replay and tests)
+ - A **config** module that reads settings we might change between flights
+ - A **recovery** module that checkpoints state so we can survive a crash
+ - A **shutdown** module that catches signals so we can stop cleanly
+ - A **telemetry** module that decides what we can afford to send to the ground
+ - A **transport** module so the io module can run over UDP and files, or in memory for testing
+ - A **router** module that works out what each incoming packet is and where it should go
+ - A **flight** module with the handlers and timers that log IMU data, update the state vector, run the controller and save checkpoints
+ - An **events** module that runs the main loop, passing messages to registered handlers and firing timers
 
 This is not too different than how we divided up the original C flight
 computer.
 
-The `main` function will simply initialize the modules and then loop forever
-listening for data.
+The `main` function will simply initialize the modules, register handlers for
+the messages we know about, and then run the event loop until it's told to
+stop.
 */
 
 extern crate byteorder;
+extern crate libc;
 
+pub mod clock;
+pub mod config;
 pub mod devices;
+pub mod events;
+pub mod flight;
 pub mod io;
+pub mod log;
 pub mod state;
 pub mod control;
+pub mod actuator;
+pub mod recovery;
+pub mod report;
+pub mod router;
+pub mod shutdown;
+pub mod telemetry;
+pub mod transport;
@@ -0,0 +1,242 @@
+/*! # Log Files
+
+Reading back the log files the flight computer writes (`logfile-NNN`).
+
+A log file is just a stream of messages, each with the same header we use for
+telemetry: a four character name, a 6 byte timestamp (nanoseconds from boot)
+and the size of the message, followed by the message itself. The first record
+is always a `SEQN` with a sequence number of zero.
+
+The `Reader` walks through a log and gives back each record, with the message
+types we know about already unpacked. If the flight computer crashed while
+writing, the last record may be cut short. That gets reported as an error at
+the end of the file rather than spoiling everything that came before it.
+*/
+
+extern crate byteorder;
+
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::time;
+use actuator;
+use control;
+use devices;
+use events;
+use io;
+use recovery;
+use shutdown;
+use state;
+use self::byteorder::{ByteOrder, BigEndian};
+
+
+/// A message from the log.
+pub enum Message {
+
+    /// IMU data (`ADIS`)
+    Adis(devices::ADIS),
+
+    /// State vector (`STAT`)
+    State(state::State),
+
+    /// Telemetry packet sequence number (`SEQN`)
+    Seqn(u32),
+
+    /// Sequence error (`SEQE`)
+    SequenceError(io::SequenceError),
+
+    /// Sequence statistics (`SEQS`)
+    SequenceStats(io::SequenceStats),
+
+    /// Link statistics (`LINK`)
+    LinkStats(io::LinkStats),
+
+    /// Send statistics (`SEND`)
+    SendStats(io::SendStats),
+
+    /// Controller terms (`CTRL`)
+    Control(control::Terms),
+
+    /// Roll actuator command (`ROLL`)
+    Roll(actuator::Roll),
+
+    /// Event loop statistics (`LOOP`)
+    LoopStats(events::LoopStats),
+
+    /// A packet we didn't recognize (`RAWP`)
+    RawPacket(io::RawPacket),
+
+    /// Restart after a crash (`RSTR`)
+    Restart(recovery::Restart),
+
+    /// Clean shutdown (`SHDN`)
+    Shutdown(shutdown::Shutdown),
+
+    /// A message we don't know how to read, as raw bytes
+    Unknown(Vec<u8>),
+}
+
+
+/// A single record from the log.
+pub struct Record {
+
+    /// Message name
+    pub name: [u8; 4],
+
+    /// Time of message (from boot)
+    pub time: time::Duration,
+
+    /// The message, as raw bytes
+    pub bytes: Vec<u8>,
+
+    /// The message
+    pub message: Message,
+}
+
+
+/// Reads records out of a log file.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::log;
+///
+/// // A log with just a header, followed by the start of a record that
+/// // got cut off
+/// let bytes = [83, 69, 81, 78, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0,
+///              65, 68, 73, 83, 0, 0];
+///
+/// let mut reader = log::Reader::new(&bytes[..]);
+///
+/// match reader.next() {
+///     Some(Ok(log::Record { message: log::Message::Seqn(0), .. })) => { },
+///     _ => panic!("expected a SEQN record"),
+/// }
+/// assert!(reader.next().unwrap().is_err());
+/// assert!(reader.next().is_none());
+/// ```
+pub struct Reader<R: Read> {
+
+    /// Where we're reading from
+    input: R,
+
+    /// Set once we hit the end (or a truncated record)
+    done: bool,
+}
+
+
+impl Reader<BufReader<File>> {
+
+    /// Open a log file for reading.
+    pub fn open(path: &str) -> Result<Reader<BufReader<File>>, Error> {
+        Ok(Reader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+
+impl<R: Read> Reader<R> {
+
+    /// Read records from any source of bytes.
+    pub fn new(input: R) -> Reader<R> {
+        Reader {
+            input,
+            done: false,
+        }
+    }
+
+    // Fill as much of the buffer as we can, returning how much we got. We
+    // only get less than we asked for at the end of the file.
+    fn read_full(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.input.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => { filled += n; },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => { },
+                Err(e) => { return Err(e); },
+            }
+        }
+        Ok(filled)
+    }
+
+    // Read the next record, or None at the end of the file
+    fn read_record(&mut self) -> Result<Option<Record>, Error> {
+
+        let mut header = [0u8; io::HEADER_SIZE];
+        match self.read_full(&mut header)? {
+            0 => { return Ok(None); },
+            io::HEADER_SIZE => { },
+            n => {
+                return Err(Error::new(ErrorKind::UnexpectedEof,
+                    format!("truncated header: expected {} bytes, got {}", io::HEADER_SIZE, n)));
+            },
+        }
+
+        let (name, time, message_size) = io::unpack_header(&header);
+
+        let mut message = vec![0u8; message_size];
+        let n = self.read_full(&mut message)?;
+        if n < message_size {
+            return Err(Error::new(ErrorKind::UnexpectedEof,
+                format!("truncated {} record: expected {} bytes, got {}", String::from_utf8_lossy(&name), message_size, n)));
+        }
+
+        Ok(Some(Record {
+            name,
+            time,
+            message: decode(name, &message),
+            bytes: message,
+        }))
+    }
+}
+
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Result<Record, Error>> {
+        if self.done {
+            return None;
+        }
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+
+/// Unpack a message we know about.
+///
+/// Anything we don't recognize, or that is the wrong size for its type, is
+/// kept as raw bytes.
+///
+/// ## Parameters:
+///
+/// - **name**: Message name
+/// - **message**: The raw message
+///
+pub fn decode(name: [u8; 4], message: &[u8]) -> Message {
+    match (name, message.len()) {
+        (devices::ADIS_NAME, devices::SIZE_OF_ADIS) => Message::Adis(devices::recv_adis(message)),
+        (state::STATE_NAME, state::SIZE_OF_STATE) => Message::State(state::State::from_message(message)),
+        (io::SEQN_NAME, 4) => Message::Seqn(BigEndian::read_u32(message)),
+        (io::SEQE_NAME, io::SIZE_OF_SEQE) => Message::SequenceError(io::SequenceError::from_message(message)),
+        (io::SEQS_NAME, io::SIZE_OF_SEQS) => Message::SequenceStats(io::SequenceStats::from_message(message)),
+        (io::LINK_NAME, io::SIZE_OF_LINK) => Message::LinkStats(io::LinkStats::from_message(message)),
+        (io::SEND_NAME, io::SIZE_OF_SEND) => Message::SendStats(io::SendStats::from_message(message)),
+        (control::CONTROL_NAME, control::SIZE_OF_CONTROL) => Message::Control(control::Terms::from_message(message)),
+        (actuator::ROLL_NAME, actuator::SIZE_OF_ROLL) => Message::Roll(actuator::Roll::from_message(message)),
+        (events::LOOP_NAME, events::SIZE_OF_LOOP) => Message::LoopStats(events::LoopStats::from_message(message)),
+        (io::RAW_PACKET_NAME, n) if n >= io::SIZE_OF_RAW_PACKET => Message::RawPacket(io::RawPacket::from_message(message)),
+        (recovery::RESTART_NAME, recovery::SIZE_OF_RESTART) => Message::Restart(recovery::Restart::from_message(message)),
+        (shutdown::SHUTDOWN_NAME, shutdown::SIZE_OF_SHUTDOWN) => Message::Shutdown(shutdown::Shutdown::from_message(message)),
+        _ => Message::Unknown(message.to_vec()),
+    }
+}
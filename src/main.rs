@@ -1,7 +1,56 @@
-mod devices;
-mod io;
-mod state;
-mod control;
+extern crate rust_fc;
+
+use rust_fc::clock::{self, Clock};
+use rust_fc::config;
+use rust_fc::devices;
+use rust_fc::events;
+use rust_fc::flight;
+use rust_fc::io;
+use rust_fc::log;
+use rust_fc::recovery;
+use rust_fc::router;
+use rust_fc::shutdown;
+use std::env;
+use std::process;
+use std::thread;
+use std::time;
+
+const USAGE: &str = r#"
+Usage: main [--replay LOGFILE [--realtime]] [CONFIG]
+
+Options:
+    --replay LOGFILE  Instead of listening to the network, run the ADIS
+                      messages from an old log through the flight computer
+                      and write a new log
+    --realtime        Replay at the speed the messages were recorded,
+                      instead of as fast as possible
+"#;
+
+
+fn fail(message: &str) -> ! {
+    println!(" {}", message);
+    print!("{}", USAGE);
+    process::exit(2);
+}
+
+
+// Wire up handlers for every message we know about, and timers for
+// everything we do regularly
+fn register(events: &mut events::EventLoop, config: &config::Config) {
+
+    // IMU data, either named or bare from the IMU's port. Log it as it came
+    // in, then update the state vector with it.
+    let logger = events.add_handler(Box::new(flight::Logger { name: devices::ADIS_NAME, size: devices::SIZE_OF_ADIS }));
+    let imu = events.add_handler(Box::new(flight::Adis));
+    for &handler in &[logger, imu] {
+        events.route_name(devices::ADIS_NAME, handler);
+        events.route_port(io::PSAS_ADIS_PORT, handler);
+    }
+
+    // Roll control
+    events.every(config.control.period(), Box::new(flight::ControlTimer::new(config)));
+}
+
 
 fn main() {
     println!(r#"
@@ -19,84 +68,152 @@ fn main() {
 
 "#);
 
-    // Set up a Flight Computer IO state
-    let mut flight_computer: io::FC = Default::default();
-
-    // New state vector
-    let mut state: state::State = Default::default();
-
-    // New controller
-    let mut controller: control::Control = Default::default();
-
-    // Track the sequence number for an ADIS message
-    let mut adis_seqn_expected = 0;
-
-
-    // The Flight Computer. Loop forever.
-    loop {
-
-        // Wait for a message from the network
-        // Then match it to the message type based on the port it came from
-        if let Some((seqn, recv_port, recv_time, message)) = flight_computer.listen() {
-            match recv_port {
-
-                // Message from ADIS IMU
-                // =====================
-                // When we get new IMU data we log it. If it's a new message then we
-                // update the state and send new data over the telemetry channel
-                io::PSAS_ADIS_PORT => {
-
-                    // We expect monotonically increasing sequence numbers.
-                    // Anything received out of order is ignored. Real time
-                    // systems can't do anything with stale data!
-                    if seqn < adis_seqn_expected {
-
-                        // Out of order packet! Log it
-                        let seqerror = io::SequenceError {
-                            port: recv_port,
-                            expected: adis_seqn_expected,
-                            received: seqn,
-                        };
-                        flight_computer.log_message(&seqerror.as_message(), io::SEQE_NAME, recv_time, io::SIZE_OF_SEQE).unwrap();
-                    }
-                    // As long as we have *new* data it's okay
-                    if seqn >= adis_seqn_expected {
-
-                        // but if it's from the future it's still an error, Log it.
-                        if seqn > adis_seqn_expected {
-                            let seqerror = io::SequenceError {
-                                port: recv_port,
-                                expected: adis_seqn_expected,
-                                received: seqn,
-                            };
-                            flight_computer.log_message(&seqerror.as_message(), io::SEQE_NAME, recv_time, io::SIZE_OF_SEQE).unwrap();
-                        }
-
-                        // Unpack binary message into proper values with units
-                        let adis = devices::recv_adis(&message);
-
-                        // Since this is IMU data, we need to update the state vector
-                        state.update_imu(recv_time, adis);
-
-                        // Do control based on new state
-                        controller.pid(&state);
-
-                        // Log ADIS and STAT. Send ADIS out over telemetry
-                        flight_computer.log_message(&message, devices::ADIS_NAME, recv_time, devices::SIZE_OF_ADIS).unwrap();
-                        flight_computer.log_message(&state.as_message(), state::STATE_NAME, recv_time, state::SIZE_OF_STATE).unwrap();
-                        flight_computer.telemetry(&message, devices::ADIS_NAME, recv_time, devices::SIZE_OF_ADIS);
-
-                        // Update sequence number counter
-                        adis_seqn_expected = seqn + 1;
-                    }
-                },
+    let mut config_path: Option<String> = None;
+    let mut replay: Option<String> = None;
+    let mut realtime = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => { replay = Some(args.next().unwrap_or_else(|| fail("--replay needs a log file"))); },
+            "--realtime" => { realtime = true; },
+            _ if arg.starts_with("--") => fail(&format!("Unknown option {}", arg)),
+            _ if config_path.is_none() => { config_path = Some(arg); },
+            _ => fail("Only one configuration file at a time"),
+        }
+    }
 
+    // Read the configuration file, if we were given one
+    let config = match config_path {
+        Some(path) => match config::Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!(" Error reading {}: {}", path, e);
+                process::exit(1);
+            },
+        },
+        None => Default::default(),
+    };
+
+    // Catch ctrl-c and friends so we can stop cleanly
+    shutdown::install().unwrap();
+
+    // Replay Mode
+    // ===========
+    // Feed the ADIS messages from an old log through the same state, control
+    // and logging path as live data, writing a new log we can compare with
+    // the old one. This doesn't touch the crash recovery checkpoint.
+    if let Some(path) = replay {
+        let reader = match log::Reader::open(&path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                println!(" Error reading {}: {}", path, e);
+                process::exit(1);
+            },
+        };
+
+        // The clock follows the log, so timestamps in the new log don't
+        // depend on how fast we replay it
+        let clock = clock::ManualClock::new();
+        let mut flight_computer = io::FC::replay(&config, Box::new(clock.clone()));
+        let mut events = events::EventLoop::new(Default::default());
+        register(&mut events, &config);
+        println!(" Replaying {} into logfile-{:03}", path, flight_computer.log_number());
+
+        // Every message in the old log that we have a handler for goes
+        // through the same handlers as live data. The log doesn't have the
+        // packets' sequence numbers, so we don't check them. Gaps in the
+        // original data show up as SEQE records in the old log but not the
+        // new one.
+        let start = time::Instant::now();
+
+        for record in reader {
+            if shutdown::requested().is_some() {
+                break;
+            }
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    println!(" {}", e);
+                    break;
+                },
+            };
+
+            // Wait until it's time for this message
+            if realtime {
+                let elapsed = start.elapsed();
+                if record.time > elapsed {
+                    thread::sleep(record.time - elapsed);
+                }
+            }
 
-                // Unknown Message Type
-                // ====================
-                // We don't know what message this is, skip it.
-                _ => { ; }
+            // Run the timers that would have run while we waited for this
+            // message, at the times they would have
+            loop {
+                let due = events.next_due(clock.now());
+                if due > record.time {
+                    break;
+                }
+                clock.set(due);
+                events.poll(&mut flight_computer);
             }
+
+            clock.set(record.time);
+            let message = router::Message {
+                name: Some(record.name),
+                port: 0,
+                seqn: 0,
+                time: record.time,
+                sent: Some(record.time),
+                payload: &record.bytes,
+            };
+            events.dispatch(&mut flight_computer, &message);
+            events.poll(&mut flight_computer);
         }
+
+        let signal = shutdown::requested().unwrap_or(0);
+        match flight_computer.shutdown(signal) {
+            Ok(_) => { process::exit(0); },
+            Err(e) => {
+                println!(" Error shutting down: {}", e);
+                process::exit(1);
+            },
+        }
+    }
+
+    // Set up a Flight Computer IO state, a new state vector and the
+    // sequence numbers we expect next from each source. If there is a
+    // checkpoint lying around then we crashed, so pick up where we left off
+    // (as long as it wasn't too long ago).
+    let (mut flight_computer, flight) = match recovery::Checkpoint::load(recovery::CHECKPOINT_FILE, config.recovery.max_downtime) {
+        Ok((checkpoint, downtime)) => {
+            println!(" Resuming from checkpoint (down for {}.{:09} s)", downtime.as_secs(), downtime.subsec_nanos());
+            (io::FC::resume(&config, &checkpoint, downtime), flight::Flight::resume(&checkpoint))
+        },
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                println!(" Ignoring checkpoint: {}", e);
+            }
+            (io::FC::new(&config), Default::default())
+        },
+    };
+    let mut events = events::EventLoop::new(flight);
+    register(&mut events, &config);
+    let checkpointer = flight::Checkpointer::new(recovery::CHECKPOINT_FILE);
+    events.every(time::Duration::from_nanos(recovery::CHECKPOINT_INTERVAL), Box::new(checkpointer));
+
+    // The Flight Computer. Loop until we're told to stop.
+    events.run(&mut flight_computer);
+
+    // Shut down cleanly. Once everything is on disk we don't need the
+    // checkpoint any more.
+    let signal = shutdown::requested().unwrap_or(0);
+    println!(" Shutting down (signal {})", signal);
+    match flight_computer.shutdown(signal).and_then(|_| recovery::clear(recovery::CHECKPOINT_FILE)) {
+        Ok(_) => { process::exit(0); },
+        Err(e) => {
+            println!(" Error shutting down: {}", e);
+            process::exit(1);
+        },
     }
 }
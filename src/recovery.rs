@@ -0,0 +1,354 @@
+/*! # Crash Recovery
+
+If the flight computer process dies and gets restarted in the middle of a
+flight we don't want to start over from the launch pad. The state vector would
+be reset to the ground and we'd lose track of which log file we were writing.
+
+So every so often we write a small checkpoint to disk with everything we need
+to pick up where we left off: the state vector (and which phase of the flight
+we're in), the log file we were writing and the sequence number counters
+(ours, and the next one we expect from each source we've heard from). A
+clean shutdown removes the checkpoint, so on startup, if there is a checkpoint
+lying around then the last run didn't finish cleanly and we resume from it.
+
+Checkpoints start with a version number. A checkpoint left behind by a
+different version of the flight computer is rejected rather than misread.
+
+A checkpoint is only any use if we come straight back up. One from before
+the machine last rebooted, or from longer ago than the configured maximum
+downtime, is most likely left over from a test on the bench, so it's ignored
+and we start fresh. The flight computer has no battery backed clock, so after
+a reboot the wall clock can't tell us how long we were down for; we check the
+kernel's boot id instead.
+*/
+
+extern crate byteorder;
+
+use std::fs;
+use std::fs::File;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::time;
+use self::byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BigEndian};
+use state;
+
+/// Where we keep the checkpoint
+pub const CHECKPOINT_FILE: &str = "fc-checkpoint";
+
+/// How often to write a checkpoint (nanoseconds)
+pub const CHECKPOINT_INTERVAL: u64 = 100000000;
+
+/// Version of the checkpoint layout. Change this whenever the layout does.
+const CHECKPOINT_VERSION: u8 = 6;
+
+/// Where the kernel keeps a random id that changes every boot
+const BOOT_ID_FILE: &str = "/proc/sys/kernel/random/boot_id";
+
+/// Size of a boot id (bytes)
+const SIZE_OF_BOOT_ID: usize = 16;
+
+/// Checkpoint file size, before the sequence numbers for each source (bytes)
+const SIZE_OF_CHECKPOINT: usize = 1 + 8 + SIZE_OF_BOOT_ID + 8 + 2 + 8 + 4 + 4 + state::SIZE_OF_STATE + 1 + 8 + 8 + 2;
+
+/// Size of the port and sequence number for each source (bytes)
+const SIZE_OF_STREAM: usize = 2 + 4;
+
+/// Restart message size (bytes)
+pub const SIZE_OF_RESTART: usize = 16;
+
+/// Restart message name (ASCII: RSTR)
+pub const RESTART_NAME: [u8;4] = [82, 83, 84, 82];
+
+
+/// Everything needed to resume flight after a restart.
+pub struct Checkpoint {
+
+    /// Flight computer time (from original boot) the checkpoint was taken
+    pub time: time::Duration,
+
+    /// Log file number we were writing to (the `NNN` in `logfile-NNN`)
+    pub log_number: u16,
+
+    /// How much of the log file was whole records (bytes). Only what comes
+    /// after this needs checking when we pick the log up again.
+    pub log_size: u64,
+
+    /// Last telemetry sequence number
+    pub telemetry_seqn: u32,
+
+    /// Next sequence number for packets to the actuator
+    pub actuator_seqn: u32,
+
+    /// Next expected sequence number from each source, by port
+    pub sequences: Vec<(u16, u32)>,
+
+    /// The state vector, including the flight phase and launch time
+    pub state: state::State,
+}
+
+
+// Wall clock time, nanoseconds since the UNIX epoch
+fn wall_clock() -> u64 {
+    match time::SystemTime::now().duration_since(time::UNIX_EPOCH) {
+        Ok(t) => (t.as_secs() * 1000000000) + t.subsec_nanos() as u64,
+        Err(_) => 0,
+    }
+}
+
+
+// Which boot of the machine this is. The kernel gives it as a UUID; we keep
+// the 16 bytes. All zeros if there's no boot id to read.
+fn boot_id() -> [u8; SIZE_OF_BOOT_ID] {
+    let mut id = [0u8; SIZE_OF_BOOT_ID];
+    if let Ok(text) = fs::read_to_string(BOOT_ID_FILE) {
+        let digits: Vec<u8> = text.chars().filter_map(|c| c.to_digit(16)).map(|d| d as u8).collect();
+        for (byte, pair) in id.iter_mut().zip(digits.chunks(2)) {
+            *byte = pair.iter().fold(0, |byte, &d| (byte << 4) | d);
+        }
+    }
+    id
+}
+
+
+impl Checkpoint {
+
+    /// Write the checkpoint to disk.
+    ///
+    /// The checkpoint is written to a temporary file first and then moved into
+    /// place, so a crash while saving leaves the previous checkpoint intact.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **path**: Where to write the checkpoint
+    ///
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut buffer = vec![0u8; SIZE_OF_CHECKPOINT + self.sequences.len() * SIZE_OF_STREAM];
+        {
+            let mut checkpoint = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Fields:
+            checkpoint.write_u8(CHECKPOINT_VERSION).unwrap();
+            checkpoint.write_u64::<BigEndian>(wall_clock()).unwrap();
+            checkpoint.write_all(&boot_id()).unwrap();
+            let nanos: u64 = (self.time.as_secs() * 1000000000) + self.time.subsec_nanos() as u64;
+            checkpoint.write_u64::<BigEndian>(nanos).unwrap();
+            checkpoint.write_u16::<BigEndian>(self.log_number).unwrap();
+            checkpoint.write_u64::<BigEndian>(self.log_size).unwrap();
+            checkpoint.write_u32::<BigEndian>(self.telemetry_seqn).unwrap();
+            checkpoint.write_u32::<BigEndian>(self.actuator_seqn).unwrap();
+            checkpoint.write_all(&self.state.as_message()).unwrap();
+            checkpoint.write_u8(self.state.phase.code()).unwrap();
+            checkpoint.write_u64::<BigEndian>(self.state.launch_time.unwrap_or(0)).unwrap();
+            checkpoint.write_f64::<BigEndian>(self.state.launch_altitude).unwrap();
+            checkpoint.write_u16::<BigEndian>(self.sequences.len() as u16).unwrap();
+            for &(port, expected) in &self.sequences {
+                checkpoint.write_u16::<BigEndian>(port).unwrap();
+                checkpoint.write_u32::<BigEndian>(expected).unwrap();
+            }
+        }
+
+        let tmp = format!("{}.tmp", path);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&buffer)?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    /// Read a checkpoint from disk.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **path**: Where the checkpoint is
+    /// - **max_downtime**: Reject the checkpoint if it was written longer ago
+    ///   than this
+    ///
+    /// ## Returns:
+    ///
+    /// The checkpoint and how long we were down for (wall clock time between
+    /// when the checkpoint was written and now). An error of kind `NotFound`
+    /// means there was no checkpoint, so the last run shut down cleanly. A
+    /// checkpoint from before the last reboot or older than `max_downtime` is
+    /// an error too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{recovery, state};
+    /// use std::fs;
+    /// use std::time::Duration;
+    ///
+    /// let path = std::env::temp_dir().join("rust-fc-checkpoint-example");
+    /// let path = path.to_str().unwrap();
+    ///
+    /// let checkpoint = recovery::Checkpoint {
+    ///     time: Duration::from_secs(12),
+    ///     log_number: 3,
+    ///     log_size: 120000,
+    ///     telemetry_seqn: 40,
+    ///     actuator_seqn: 700,
+    ///     sequences: vec![(35020, 9000), (35030, 12)],
+    ///     state: state::State { phase: state::Phase::Coast, launch_time: Some(5000000000), ..Default::default() },
+    /// };
+    /// checkpoint.save(path).unwrap();
+    ///
+    /// let (loaded, _) = recovery::Checkpoint::load(path, Duration::from_secs(10)).unwrap();
+    /// assert_eq!(loaded.log_number, 3);
+    /// assert_eq!(loaded.log_size, 120000);
+    /// assert_eq!(loaded.actuator_seqn, 700);
+    /// assert_eq!(loaded.state.phase, state::Phase::Coast);
+    /// assert_eq!(loaded.state.launch_time, Some(5000000000));
+    /// assert_eq!(loaded.sequences, vec![(35020, 9000), (35030, 12)]);
+    ///
+    /// // A checkpoint from a long time ago, or from before a reboot, is
+    /// // stale. Anything else is rejected rather than misread.
+    /// let bytes = fs::read(path).unwrap();
+    /// let mut old = bytes.clone();
+    /// old[1..9].copy_from_slice(&[0; 8]);
+    /// fs::write(path, &old).unwrap();
+    /// assert!(recovery::Checkpoint::load(path, Duration::from_secs(10)).is_err());
+    /// let mut other_boot = bytes.clone();
+    /// other_boot[9..25].copy_from_slice(&[0xff; 16]);
+    /// fs::write(path, &other_boot).unwrap();
+    /// assert!(recovery::Checkpoint::load(path, Duration::from_secs(10)).is_err());
+    /// fs::write(path, &bytes[..bytes.len() - 9]).unwrap();
+    /// assert!(recovery::Checkpoint::load(path, Duration::from_secs(10)).is_err());
+    /// recovery::clear(path).unwrap();
+    /// ```
+    pub fn load(path: &str, max_downtime: time::Duration) -> Result<(Checkpoint, time::Duration), Error> {
+        let mut buffer = Vec::with_capacity(SIZE_OF_CHECKPOINT);
+        File::open(path)?.read_to_end(&mut buffer)?;
+        if buffer.first() != Some(&CHECKPOINT_VERSION) {
+            return Err(Error::new(ErrorKind::InvalidData, "checkpoint is from a different version"));
+        }
+        if buffer.len() < SIZE_OF_CHECKPOINT {
+            return Err(Error::new(ErrorKind::InvalidData, "checkpoint is the wrong size"));
+        }
+        let streams = BigEndian::read_u16(&buffer[SIZE_OF_CHECKPOINT - 2..]) as usize;
+        if buffer.len() != SIZE_OF_CHECKPOINT + streams * SIZE_OF_STREAM {
+            return Err(Error::new(ErrorKind::InvalidData, "checkpoint is the wrong size"));
+        }
+
+        let mut checkpoint = Cursor::new(&buffer[..]);
+        checkpoint.read_u8().unwrap();
+        let wall_time = checkpoint.read_u64::<BigEndian>().unwrap();
+        let mut boot = [0u8; SIZE_OF_BOOT_ID];
+        checkpoint.read_exact(&mut boot).unwrap();
+        let nanos = checkpoint.read_u64::<BigEndian>().unwrap();
+
+        if boot != boot_id() {
+            return Err(Error::new(ErrorKind::InvalidData, "checkpoint is from before the last reboot"));
+        }
+        let downtime = wall_clock().saturating_sub(wall_time);
+        let downtime = time::Duration::new(downtime / 1000000000, (downtime % 1000000000) as u32);
+        if downtime > max_downtime {
+            return Err(Error::new(ErrorKind::InvalidData, format!("checkpoint is {} s old", downtime.as_secs())));
+        }
+
+        let log_number = checkpoint.read_u16::<BigEndian>().unwrap();
+        let log_size = checkpoint.read_u64::<BigEndian>().unwrap();
+        let telemetry_seqn = checkpoint.read_u32::<BigEndian>().unwrap();
+        let actuator_seqn = checkpoint.read_u32::<BigEndian>().unwrap();
+
+        let mut state_message = [0u8; state::SIZE_OF_STATE];
+        checkpoint.read_exact(&mut state_message).unwrap();
+        let mut state = state::State::from_message(&state_message);
+
+        // The phase, launch time and launch altitude go after the state vector
+        state.phase = match state::Phase::from_code(checkpoint.read_u8().unwrap()) {
+            Some(phase) => phase,
+            None => { return Err(Error::new(ErrorKind::InvalidData, "checkpoint has an unknown flight phase")); },
+        };
+        let launch_time = checkpoint.read_u64::<BigEndian>().unwrap();
+        if state.phase != state::Phase::Pad {
+            state.launch_time = Some(launch_time);
+        }
+        state.launch_altitude = checkpoint.read_f64::<BigEndian>().unwrap();
+
+        checkpoint.read_u16::<BigEndian>().unwrap();
+        let sequences = (0..streams).map(|_| {
+            let port = checkpoint.read_u16::<BigEndian>().unwrap();
+            (port, checkpoint.read_u32::<BigEndian>().unwrap())
+        }).collect();
+
+        Ok((Checkpoint {
+            time: time::Duration::new(nanos / 1000000000, (nanos % 1000000000) as u32),
+            log_number,
+            log_size,
+            telemetry_seqn,
+            actuator_seqn,
+            sequences,
+            state,
+        }, downtime))
+    }
+}
+
+
+/// Remove the checkpoint.
+///
+/// Called on a clean shutdown so the next run starts fresh. It's not an error
+/// if there was no checkpoint to remove.
+pub fn clear(path: &str) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+
+/// A restart message.
+///
+/// Logged when we come back up after an unclean shutdown, so we know there is
+/// a hole in the data and how big it is.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::recovery;
+///
+/// let restart = recovery::Restart {
+///     downtime: 250000000,
+///     resumed_from: 12000000000,
+/// };
+///
+/// assert_eq!(restart.as_message().len(), recovery::SIZE_OF_RESTART);
+/// ```
+pub struct Restart {
+
+    /// How long we were down for [ns]
+    pub downtime: u64,
+
+    /// Time of the checkpoint we resumed from [ns from boot]
+    pub resumed_from: u64,
+}
+
+
+impl Restart {
+
+    /// Read a restart message back from bytes.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> Restart {
+        let mut message = Cursor::new(message_buffer);
+
+        Restart {
+            downtime:     message.read_u64::<BigEndian>().unwrap(),
+            resumed_from: message.read_u64::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_RESTART] {
+        let mut buffer = [0u8; SIZE_OF_RESTART];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u64::<BigEndian>(self.downtime).unwrap();
+            message.write_u64::<BigEndian>(self.resumed_from).unwrap();
+        }
+        buffer
+    }
+}
@@ -0,0 +1,250 @@
+/*! # Flight Report
+
+After every flight we want the same handful of numbers: how high and how fast
+did we go, when was apogee, how long did the motor burn, and how healthy were
+the data links. The `Summary` collects these while walking through the records
+of a log file.
+*/
+
+use std::time;
+use io;
+use log;
+use state;
+
+
+/// A peak value and when it happened.
+#[derive(Clone, Copy)]
+pub struct Peak {
+
+    /// The value
+    pub value: f64,
+
+    /// Time of the peak (from boot)
+    pub time: time::Duration,
+}
+
+
+/// A summary of a flight.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{devices, io, log, recovery, report};
+/// use std::time::Duration;
+///
+/// let imu = |acc_x| devices::ADIS {
+///     vcc: 5.0, gyro_x: 0.0, gyro_y: 0.0, gyro_z: 0.0,
+///     acc_x, acc_y: 0.0, acc_z: 0.0,
+///     magn_x: 0.0, magn_y: 0.0, magn_z: 0.0, temp: 300.0,
+/// };
+/// let record = |ms, message| log::Record { name: [0; 4], time: Duration::from_millis(ms), bytes: Vec::new(), message };
+///
+/// // A log in memory: sitting on the pad (with one bump at 50 ms), a motor
+/// // that lights at 100 ms and burns out at 1100 ms, then coasting
+/// let mut records = Vec::new();
+/// for ms in (0..1500).step_by(10) {
+///     let acc_x = match ms {
+///         50 => 100.0,
+///         _ if ms < 100 => 9.8,
+///         _ if ms < 1100 => 59.8,
+///         _ => -0.2,
+///     };
+///     records.push(record(ms, log::Message::Adis(imu(acc_x))));
+/// }
+///
+/// // Five IMU packets went missing but two turned up late. Then we crashed
+/// // and lost one more after restarting.
+/// let stats = |port, lost| io::SequenceStats { port, lost, ..Default::default() };
+/// records.push(record(1500, log::Message::SequenceError(io::SequenceError { port: io::PSAS_ADIS_PORT, expected: 10, received: 15 })));
+/// records.push(record(1500, log::Message::SequenceStats(stats(io::PSAS_ADIS_PORT, 3))));
+/// records.push(record(1500, log::Message::SequenceStats(stats(35030, 100))));
+/// records.push(record(1600, log::Message::Restart(recovery::Restart { downtime: 50000000, resumed_from: 1500000000 })));
+/// records.push(record(2600, log::Message::SequenceStats(stats(io::PSAS_ADIS_PORT, 1))));
+///
+/// let mut summary: report::Summary = Default::default();
+/// for record in &records {
+///     summary.add(record);
+/// }
+///
+/// assert_eq!(summary.adis_packets, 150);
+/// assert_eq!(summary.burn_start, Some(Duration::from_millis(100)));
+/// assert_eq!(summary.burn_end, Some(Duration::from_millis(1140)));
+/// assert_eq!(summary.adis_lost, 4);
+/// assert_eq!(summary.restarts, 1);
+/// ```
+pub struct Summary {
+
+    /// Port the IMU sends from. Only sequence errors from here count as lost
+    /// or out of order ADIS packets.
+    pub imu_port: u16,
+
+    /// Altitude [m] at the start of the log (the launch site)
+    pub launch_altitude: Option<f64>,
+
+    /// Highest altitude [m]. The time of this is apogee.
+    pub max_altitude: Option<Peak>,
+
+    /// Fastest vertical velocity [m/s]
+    pub max_velocity: Option<Peak>,
+
+    /// Largest vertical acceleration [m/s²]
+    pub max_acceleration: Option<Peak>,
+
+    /// When we launched, by the flight computer's own rules for going from
+    /// the pad to boost
+    pub burn_start: Option<time::Duration>,
+
+    /// When the motor burned out, by the flight computer's own rules for
+    /// going from boost to coast
+    pub burn_end: Option<time::Duration>,
+
+    /// Number of ADIS messages in the log
+    pub adis_packets: u64,
+
+    /// ADIS packets we never got, going by the flight computer's sequence
+    /// statistics (so packets that turned up late don't count). Logs from
+    /// before there were sequence statistics only have the gaps.
+    pub adis_lost: u64,
+
+    /// ADIS packets that arrived out of order and were thrown away
+    pub adis_stale: u64,
+
+    /// Number of telemetry packets sent
+    pub telemetry_packets: u32,
+
+    /// Number of times the flight computer restarted after a crash
+    pub restarts: u32,
+
+    /// Time of the last record in the log
+    pub duration: time::Duration,
+
+    /// ADIS packets lost before the last restart. Sequence statistics start
+    /// over from zero when the flight computer restarts.
+    lost_before_restart: u64,
+
+    /// Have we seen sequence statistics for the IMU?
+    have_stats: bool,
+
+    /// The IMU data run through a state vector again, to find the flight
+    /// phases the same way the flight computer did
+    state: state::State,
+}
+
+
+impl Default for Summary {
+    fn default () -> Summary {
+        Summary {
+            imu_port: io::PSAS_ADIS_PORT,
+            launch_altitude: None,
+            max_altitude: None,
+            max_velocity: None,
+            max_acceleration: None,
+            burn_start: None,
+            burn_end: None,
+            adis_packets: 0,
+            adis_lost: 0,
+            adis_stale: 0,
+            telemetry_packets: 0,
+            restarts: 0,
+            duration: Default::default(),
+            lost_before_restart: 0,
+            have_stats: false,
+            state: Default::default(),
+        }
+    }
+}
+
+
+// Keep track of the largest value we've seen
+fn peak(current: &mut Option<Peak>, value: f64, time: time::Duration) {
+    match *current {
+        Some(ref p) if p.value >= value => { },
+        _ => { *current = Some(Peak { value, time }); },
+    }
+}
+
+
+impl Summary {
+
+    /// Add a record from the log to the summary.
+    pub fn add(&mut self, record: &log::Record) {
+
+        if record.time > self.duration {
+            self.duration = record.time;
+        }
+
+        match record.message {
+            log::Message::State(ref state) => {
+                if self.launch_altitude.is_none() {
+                    self.launch_altitude = Some(state.altitude);
+                }
+                peak(&mut self.max_altitude, state.altitude, record.time);
+                peak(&mut self.max_velocity, state.vel_up, record.time);
+                peak(&mut self.max_acceleration, state.acc_up, record.time);
+            },
+            log::Message::Adis(ref adis) => {
+                self.adis_packets += 1;
+
+                // Burn time. A single sample is too noisy to go by, so wait
+                // for the phase to change like the flight computer does. It
+                // only changes once the condition has held for a while, so
+                // the burn ended when it started holding.
+                let (phase, pending) = (self.state.phase, self.state.phase_pending);
+                self.state.update_imu(record.time, adis.clone());
+                match (phase, self.state.phase) {
+                    (state::Phase::Pad, state::Phase::Boost) => {
+                        self.burn_start = self.state.launch_time.map(time::Duration::from_nanos);
+                    },
+                    (state::Phase::Boost, state::Phase::Coast) => {
+                        self.burn_end = Some(pending.map(time::Duration::from_nanos).unwrap_or(record.time));
+                    },
+                    _ => { },
+                }
+            },
+            log::Message::SequenceError(ref seqe) if seqe.port == self.imu_port => {
+                // Same rules as the sequence tracker: a jump forward is a
+                // gap, a little way back is stale, and anything further is the
+                // sender restarting
+                let ahead = seqe.received.wrapping_sub(seqe.expected);
+                let behind = seqe.expected.wrapping_sub(seqe.received);
+                if ahead <= io::SEQUENCE_MAX_GAP {
+                    if !self.have_stats {
+                        self.adis_lost += ahead as u64;
+                    }
+                }
+                else if behind <= io::SEQUENCE_WINDOW {
+                    self.adis_stale += 1;
+                }
+            },
+            log::Message::SequenceStats(ref stats) if stats.port == self.imu_port => {
+                self.have_stats = true;
+                self.adis_lost = self.lost_before_restart + stats.lost as u64;
+            },
+            log::Message::Seqn(seqn) if seqn > self.telemetry_packets => { self.telemetry_packets = seqn; },
+            log::Message::Restart(_) => {
+                // Like the flight computer, don't integrate over the gap
+                self.restarts += 1;
+                self.lost_before_restart = self.adis_lost;
+                self.state.integrating = false;
+            },
+            _ => { },
+        }
+    }
+
+    /// How long the motor burned for, if we saw it burn out.
+    pub fn burn_time(&self) -> Option<time::Duration> {
+        match (self.burn_start, self.burn_end) {
+            (Some(start), Some(end)) => Some(end - start),
+            _ => None,
+        }
+    }
+
+    /// Fraction of ADIS packets lost (0 to 1).
+    pub fn adis_loss(&self) -> f64 {
+        let expected = self.adis_packets + self.adis_lost;
+        if expected == 0 {
+            return 0.0;
+        }
+        self.adis_lost as f64 / expected as f64
+    }
+}
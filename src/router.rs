@@ -0,0 +1,284 @@
+/*! # Message Router
+
+Works out what each incoming packet is and who should deal with it.
+
+Packets on the rocket's network start with a sequence number. Most devices
+then send a PSAS message: the same header we use for the log and telemetry (a
+four character name, a timestamp and a size) followed by the message itself.
+Some older devices skip the header and send the bare message, and the only way
+to tell what it is is which port it came from.
+
+A packet can hold several messages, each with its own header (like our
+telemetry packets), so sensors can send a batch of samples at once. A packet
+that starts with a header but doesn't split up into messages is corrupt, and
+is never taken for a bare message.
+
+A route can be for a message name, a port, or both:
+
+ - A message with a header goes to the route for its name *and* port if there
+   is one, and otherwise the route for its name. That way one device can send
+   several kinds of message, and it doesn't matter which port it sends from.
+ - A bare message goes to the route for its port.
+*/
+
+use std::collections::HashMap;
+use std::time;
+use io;
+
+
+/// A message name, the timestamp from its header, and the message
+type Record<'a> = ([u8; 4], time::Duration, &'a [u8]);
+
+
+/// A message pulled out of a packet.
+pub struct Message<'a> {
+
+    /// Message name, if the packet had a PSAS header
+    pub name: Option<[u8; 4]>,
+
+    /// Which port the packet came from
+    pub port: u16,
+
+    /// Sequence number of the packet
+    pub seqn: u32,
+
+    /// When the message was taken, on our clock. For a packet with several
+    /// messages in it the last one is when we got the packet, and the others
+    /// are spaced out before it going by the timestamps in their headers.
+    pub time: time::Duration,
+
+    /// Timestamp from the header (on the sender's clock), if it had one
+    pub sent: Option<time::Duration>,
+
+    /// The message itself, without any header
+    pub payload: &'a [u8],
+}
+
+
+// Could this be a message name? Names are upper case letters and digits.
+fn is_name(name: &[u8]) -> bool {
+    name.iter().all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+
+// Split the bytes after the sequence number into PSAS messages. Every header
+// has to have a proper name and a size that fits in what's left, and the
+// messages have to use up the whole packet, otherwise it's not PSAS messages.
+fn split<'a>(bytes: &'a [u8]) -> Option<Vec<Record<'a>>> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        if rest.len() < io::HEADER_SIZE || !is_name(&rest[..4]) {
+            return None;
+        }
+        let (name, sent, size) = io::unpack_header(rest);
+        if size > rest.len() - io::HEADER_SIZE {
+            return None;
+        }
+        records.push((name, sent, &rest[io::HEADER_SIZE..io::HEADER_SIZE + size]));
+        offset += io::HEADER_SIZE + size;
+    }
+
+    if records.is_empty() {
+        return None;
+    }
+    Some(records)
+}
+
+
+/// Read the messages out of a packet.
+///
+/// After the sequence number, a packet is either a run of PSAS messages
+/// (each a header and then the message, just like we send in telemetry) or a
+/// single bare message. If it starts with a message name it has to split up
+/// cleanly into PSAS messages. Otherwise it's a bare message.
+///
+/// ## Returns:
+///
+/// The messages in the order they were in the packet. Nothing if the packet
+/// is too short to even have a sequence number, or if it starts with a header
+/// but doesn't split up into messages (a corrupt or cut off batch, which we
+/// mustn't take for bare data).
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::{io, router};
+/// use std::time::Duration;
+///
+/// // Two messages, sent 2 ms apart
+/// let mut packet = io::Packet {
+///     from: "127.0.0.1:35020".parse().unwrap(),
+///     time: Duration::from_millis(100),
+///     bytes: vec![0, 0, 0, 7,
+///                 71, 80, 83, 49, 0, 0, 0, 0x1e, 0x84, 0x80, 0, 2, 1, 2,
+///                 71, 80, 83, 49, 0, 0, 0, 0x3d, 0x09, 0x00, 0, 1, 3],
+/// };
+///
+/// let messages = router::messages(&packet);
+/// assert_eq!(messages.len(), 2);
+/// assert_eq!(messages[0].name, Some(*b"GPS1"));
+/// assert_eq!(messages[0].seqn, 7);
+/// assert_eq!(messages[0].payload, &[1, 2]);
+/// assert_eq!(messages[0].time, Duration::from_millis(98));
+/// assert_eq!(messages[1].payload, &[3]);
+/// assert_eq!(messages[1].time, Duration::from_millis(100));
+///
+/// // Without headers
+/// packet.bytes = vec![0, 0, 0, 8, 1, 2, 3];
+/// let messages = router::messages(&packet);
+/// assert_eq!(messages[0].name, None);
+/// assert_eq!(messages[0].payload, &[1, 2, 3]);
+///
+/// // A header for more than is there
+/// packet.bytes = vec![0, 0, 0, 9, 71, 80, 83, 49, 0, 0, 0, 0, 0, 0, 0, 2, 1];
+/// assert!(router::messages(&packet).is_empty());
+/// ```
+pub fn messages<'a>(packet: &'a io::Packet) -> Vec<Message<'a>> {
+    let seqn = match packet.seqn() {
+        Some(seqn) => seqn,
+        None => { return Vec::new(); },
+    };
+    let port = packet.from.port();
+    let rest = packet.message();
+
+    let records = match split(rest) {
+        Some(records) => records,
+        None if rest.len() >= 4 && is_name(&rest[..4]) => { return Vec::new(); },
+        None => {
+            return vec![Message {
+                name: None,
+                port,
+                seqn,
+                time: packet.time,
+                sent: None,
+                payload: rest,
+            }];
+        },
+    };
+
+    // Line up the sender's clock with ours at the newest message
+    let latest = records.iter().map(|&(_, sent, _)| sent).max().unwrap();
+
+    records.into_iter().map(|(name, sent, payload)| Message {
+        name: Some(name),
+        port,
+        seqn,
+        time: packet.time.checked_sub(latest - sent).unwrap_or_default(),
+        sent: Some(sent),
+        payload,
+    }).collect()
+}
+
+
+/// Where messages should go.
+///
+/// A router can hold anything as the "handler" for a route: a function, a
+/// trait object, or just a tag to `match` on. To send a message to several
+/// handlers, hold a list of them and add to it with the `*_entry` functions.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::router;
+///
+/// let mut router = router::Router::new();
+/// router.add_name(*b"ADIS", "imu");
+/// router.add_port(35020, "old imu");
+/// router.add(*b"GPS1", 35030, "gps on the nose");
+///
+/// assert_eq!(router.route(Some(*b"ADIS"), 36001), Some(&"imu"));
+/// assert_eq!(router.route(None, 35020), Some(&"old imu"));
+/// assert_eq!(router.route(Some(*b"GPS1"), 35030), Some(&"gps on the nose"));
+/// assert_eq!(router.route(Some(*b"GPS1"), 35031), None);
+///
+/// // Several handlers for one name
+/// let mut router: router::Router<Vec<&str>> = router::Router::new();
+/// router.name_entry(*b"ADIS").push("logger");
+/// router.name_entry(*b"ADIS").push("estimator");
+/// assert_eq!(router.route(Some(*b"ADIS"), 35020), Some(&vec!["logger", "estimator"]));
+/// ```
+pub struct Router<H> {
+
+    /// Routes for a name from a particular port
+    by_name_and_port: HashMap<([u8; 4], u16), H>,
+
+    /// Routes for a name from anywhere
+    by_name: HashMap<[u8; 4], H>,
+
+    /// Routes for bare messages from a port
+    by_port: HashMap<u16, H>,
+}
+
+
+impl<H> Default for Router<H> {
+    fn default () -> Router<H> {
+        Router::new()
+    }
+}
+
+
+impl<H> Router<H> {
+
+    /// A router with no routes.
+    pub fn new() -> Router<H> {
+        Router {
+            by_name_and_port: HashMap::new(),
+            by_name: HashMap::new(),
+            by_port: HashMap::new(),
+        }
+    }
+
+    /// Route messages with this name, whichever port they come from.
+    pub fn add_name(&mut self, name: [u8; 4], handler: H) {
+        self.by_name.insert(name, handler);
+    }
+
+    /// Route bare messages (with no PSAS header) from this port.
+    pub fn add_port(&mut self, port: u16, handler: H) {
+        self.by_port.insert(port, handler);
+    }
+
+    /// Route messages with this name, but only from this port.
+    pub fn add(&mut self, name: [u8; 4], port: u16, handler: H) {
+        self.by_name_and_port.insert((name, port), handler);
+    }
+
+    /// Find the route for a message.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **name**: Message name, or None for a bare message
+    /// - **port**: Which port the message came from
+    ///
+    pub fn route(&self, name: Option<[u8; 4]>, port: u16) -> Option<&H> {
+        match name {
+            Some(name) => self.by_name_and_port.get(&(name, port)).or_else(|| self.by_name.get(&name)),
+            None => self.by_port.get(&port),
+        }
+    }
+}
+
+
+impl<H: Default> Router<H> {
+
+    /// The route for messages with this name, whichever port they come from.
+    /// If there isn't one yet it starts out empty.
+    pub fn name_entry(&mut self, name: [u8; 4]) -> &mut H {
+        self.by_name.entry(name).or_default()
+    }
+
+    /// The route for bare messages from this port. If there isn't one yet it
+    /// starts out empty.
+    pub fn port_entry(&mut self, port: u16) -> &mut H {
+        self.by_port.entry(port).or_default()
+    }
+
+    /// The route for messages with this name from this port. If there isn't
+    /// one yet it starts out empty.
+    pub fn entry(&mut self, name: [u8; 4], port: u16) -> &mut H {
+        self.by_name_and_port.entry((name, port)).or_default()
+    }
+}
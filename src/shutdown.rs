@@ -0,0 +1,115 @@
+/*! # Shutdown
+
+The flight computer runs until it's told to stop. On the ground that usually
+means someone hitting ctrl-c, or the process being killed by whatever started
+it. Rather than dying mid-write we catch `SIGINT` and `SIGTERM`, note that a
+shutdown was requested, and let the main loop finish up cleanly: send the last
+partial telemetry packet, log a shutdown message and make sure everything is
+on disk.
+*/
+
+extern crate byteorder;
+extern crate libc;
+
+use std::io::{Cursor, Error};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+
+/// Shutdown message size (bytes)
+pub const SIZE_OF_SHUTDOWN: usize = 4;
+
+/// Shutdown message name (ASCII: SHDN)
+pub const SHUTDOWN_NAME: [u8;4] = [83, 72, 68, 78];
+
+// The signal we caught (zero if we haven't caught one)
+static SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+// Signal handler. The only thing that's safe to do in here is set a flag.
+extern "C" fn handler(signal: libc::c_int) {
+    SIGNAL.store(signal as usize, Ordering::SeqCst);
+}
+
+
+/// Install the signal handlers.
+///
+/// The handlers are installed *without* `SA_RESTART`, so a blocking read on a
+/// socket returns early with an interrupted error when a signal comes in. That
+/// way the main loop gets a chance to notice it's time to stop.
+pub fn install() -> Result<(), Error> {
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(*signal, &action, ptr::null_mut()) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Has someone asked us to stop?
+///
+/// ## Returns:
+///
+/// The signal number that was caught, if any.
+pub fn requested() -> Option<i32> {
+    match SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal as i32),
+    }
+}
+
+
+/// A shutdown message.
+///
+/// The last thing written to the log on a clean exit.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::shutdown;
+///
+/// let message = shutdown::Shutdown { signal: 2 };
+///
+/// assert_eq!(message.as_message(), [0, 0, 0, 2]);
+/// ```
+pub struct Shutdown {
+
+    /// The signal that asked us to stop
+    pub signal: u32,
+}
+
+
+impl Shutdown {
+
+    /// Read a shutdown message back from bytes.
+    ///
+    /// This is the inverse of `as_message`.
+    pub fn from_message(message_buffer: &[u8]) -> Shutdown {
+        let mut message = Cursor::new(message_buffer);
+
+        Shutdown {
+            signal: message.read_u32::<BigEndian>().unwrap(),
+        }
+    }
+
+    /// Return a copy of this struct as a byte array.
+    ///
+    /// The PSAS file and message type is always a byte array with big-endian
+    /// representation of fields in a struct.
+    pub fn as_message(&self) -> [u8; SIZE_OF_SHUTDOWN] {
+        let mut buffer = [0u8; SIZE_OF_SHUTDOWN];
+        {
+            let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
+
+            // Struct Fields:
+            message.write_u32::<BigEndian>(self.signal).unwrap();
+        }
+        buffer
+    }
+}
@@ -1,12 +1,22 @@
 /*! # State Vector
 
 Tracking state of the rocket.
+
+Along with where the rocket is and how it's moving, we keep track of which
+phase of the flight we're in. We go from the pad to boost when the motor
+lights, to coast at burnout, and to descent at apogee, and never back.
+
+Since we never go back, one noisy sample mustn't be enough to move on: the
+IMU sees plenty of vibration on the rail and under thrust. Each transition
+has to hold for a while before we believe it (going by a low pass filtered
+acceleration, so the odd sample doesn't start the wait over), burnout can't
+come too soon after launch, and apogee has to come after a real climb.
 */
 extern crate byteorder;
 
 use std::time;
 use std::io::Cursor;
-use self::byteorder::{WriteBytesExt, BigEndian};
+use self::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
 use devices;
 
 /// State message size (bytes)
@@ -15,8 +25,75 @@ pub const SIZE_OF_STATE: usize = 48;
 /// State message name (ASCII: STAT)
 pub const STATE_NAME: [u8;4] = [83, 84, 65, 84];
 
+/// Vertical acceleration that means we've left the pad [m/s²]
+const LAUNCH_ACCELERATION: f64 = 20.0;
+
+/// Time constant of the filter on acceleration for finding flight phases
+/// [s]
+const PHASE_FILTER: f64 = 0.02;
+
+/// How long the acceleration has to stay over `LAUNCH_ACCELERATION` before
+/// we call it a launch [ns]
+const LAUNCH_HOLD: u64 = 50000000;
+
+/// Shortest the motor can burn for [ns]
+const MIN_BOOST_TIME: u64 = 500000000;
+
+/// How long we have to be slowing down before we call it burnout [ns]
+const BURNOUT_HOLD: u64 = 100000000;
+
+/// How far we have to have climbed before we look for apogee [m]
+const MIN_CLIMB: f64 = 50.0;
+
+/// How long we have to be going down before we call it apogee [ns]
+const APOGEE_HOLD: u64 = 250000000;
+
+
+/// Phase of the flight.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Phase {
+
+    /// Sitting on the pad (or the rail)
+    Pad,
+
+    /// Motor burning
+    Boost,
+
+    /// Burnout to apogee
+    Coast,
+
+    /// Apogee and after
+    Descent,
+}
+
+
+impl Phase {
+
+    /// Phase from its number in a checkpoint.
+    pub fn from_code(code: u8) -> Option<Phase> {
+        match code {
+            0 => Some(Phase::Pad),
+            1 => Some(Phase::Boost),
+            2 => Some(Phase::Coast),
+            3 => Some(Phase::Descent),
+            _ => None,
+        }
+    }
+
+    /// Number for the phase in a checkpoint.
+    pub fn code(&self) -> u8 {
+        match *self {
+            Phase::Pad => 0,
+            Phase::Boost => 1,
+            Phase::Coast => 2,
+            Phase::Descent => 3,
+        }
+    }
+}
+
 
 /// A representation for the current state vector in MKS units.
+#[derive(Clone)]
 pub struct State {
 
     /// Exact time (nanoseconds from boot) that state-vector is valid for.
@@ -36,6 +113,30 @@ pub struct State {
 
     /// Roll angle [deg] (zero is initial angle)
 	pub roll_angle: f64,
+
+    /// Phase of the flight. This isn't in the STAT message.
+	pub phase: Phase,
+
+    /// Time (nanoseconds from boot) we launched, if we have. This isn't in
+    /// the STAT message.
+	pub launch_time: Option<u64>,
+
+    /// Altitude we launched from [m]. This isn't in the STAT message.
+	pub launch_altitude: f64,
+
+    /// Vertical acceleration, low pass filtered for finding flight phases
+    /// [m/s²]. This isn't in the STAT message.
+	pub acc_filtered: f64,
+
+    /// Time (nanoseconds from boot) the condition for the next phase started
+    /// holding, if it is. This isn't in the STAT message.
+	pub phase_pending: Option<u64>,
+
+    /// Whether the next sample is integrated from this one. Not until we've
+    /// had a first sample, and not straight after resuming from a
+    /// checkpoint, or we'd integrate over the time we were down. This isn't
+    /// in the STAT message.
+	pub integrating: bool,
 }
 
 /// Initialize State Vector to Launch Site.
@@ -48,6 +149,12 @@ impl Default for State {
             altitude: 1390.0,
             roll_rate: 0.0,
             roll_angle: 0.0,
+            phase: Phase::Pad,
+            launch_time: None,
+            launch_altitude: 1390.0,
+            acc_filtered: 0.0,
+            phase_pending: None,
+            integrating: false,
         }
     }
 }
@@ -60,17 +167,102 @@ impl State {
     /// This is expected to be called every time new data is available from
     /// a sensor.
     ///
+    /// Launch is when the vertical acceleration (less gravity, and filtered)
+    /// stays over 20 m/s² for 50 ms. Burnout is when it stays negative for
+    /// 100 ms, at least half a second after launch. Apogee is when the
+    /// vertical velocity stays negative for 250 ms, after we've climbed at
+    /// least 50 m.
+    ///
     /// ## Parameters:
     ///
     /// - **time**: Time that new data is valid for
     /// - **imu**: New IMU data
     ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_fc::{devices, state};
+    /// use std::time::Duration;
+    ///
+    /// let imu = |acc_x, gyro_x| devices::ADIS { vcc: 5.0, gyro_x: gyro_x, gyro_y: 0.0, gyro_z: 0.0,
+    ///     acc_x: acc_x, acc_y: 0.0, acc_z: 0.0, magn_x: 0.0, magn_y: 0.0, magn_z: 0.0, temp: 20.0 };
+    ///
+    /// // A sample every millisecond
+    /// let mut state: state::State = Default::default();
+    /// let mut ms = 0;
+    /// let mut fly = |state: &mut state::State, acc_x: f64, gyro_x: f64| {
+    ///     ms += 1;
+    ///     state.update_imu(Duration::from_millis(ms), imu(acc_x, gyro_x));
+    /// };
+    ///
+    /// // A noisy pad, with someone bumping the rail
+    /// for i in 0..200 {
+    ///     let bump = if i == 100 { 60.0 } else { 0.0 };
+    ///     fly(&mut state, 9.8 + if i % 2 == 0 { 1.0 } else { -1.0 } + bump, 0.0);
+    /// }
+    /// assert_eq!(state.phase, state::Phase::Pad);
+    ///
+    /// // Motor lights, and shakes the IMU around
+    /// for i in 0..800 {
+    ///     let acc_x = if i % 10 == 9 { 5.0 } else { 100.0 };
+    ///     fly(&mut state, acc_x, 90.0);
+    /// }
+    /// assert_eq!(state.phase, state::Phase::Boost);
+    ///
+    /// // Launch is within a few samples of the motor lighting
+    /// let launch = state.launch_time.unwrap();
+    /// assert!(launch > 200000000 && launch < 210000000);
+    ///
+    /// // Burnout
+    /// for _ in 0..200 {
+    ///     fly(&mut state, 0.0, 0.0);
+    /// }
+    /// assert_eq!(state.phase, state::Phase::Coast);
+    ///
+    /// // Coast up to apogee
+    /// while state.vel_up > 0.0 {
+    ///     fly(&mut state, 0.0, 0.0);
+    /// }
+    /// assert_eq!(state.phase, state::Phase::Coast);
+    /// for _ in 0..300 {
+    ///     fly(&mut state, 0.0, 0.0);
+    /// }
+    /// assert_eq!(state.phase, state::Phase::Descent);
+    ///
+    /// // Resuming a minute later doesn't integrate over the gap
+    /// let (altitude, vel_up) = (state.altitude, state.vel_up);
+    /// state.integrating = false;
+    /// state.update_imu(Duration::from_secs(60), imu(0.0, 0.0));
+    /// assert_eq!((state.altitude, state.vel_up), (altitude, vel_up));
+    /// ```
+    ///
+    /// Samples from a batch, or from a replay, can be stamped a little out of
+    /// order. One from before the condition started holding doesn't cut the
+    /// wait short:
+    ///
+    /// ```
+    /// use rust_fc::{devices, state};
+    /// use std::time::Duration;
+    ///
+    /// let imu = || devices::ADIS { vcc: 5.0, gyro_x: 0.0, gyro_y: 0.0, gyro_z: 0.0,
+    ///     acc_x: 100.0, acc_y: 0.0, acc_z: 0.0, magn_x: 0.0, magn_y: 0.0, magn_z: 0.0, temp: 20.0 };
+    ///
+    /// let mut state: state::State = Default::default();
+    /// for ms in 200..215 {
+    ///     state.update_imu(Duration::from_millis(ms), imu());
+    /// }
+    /// assert!(state.phase_pending.unwrap() > 201000000);
+    ///
+    /// state.update_imu(Duration::from_millis(201), imu());
+    /// assert_eq!(state.phase, state::Phase::Pad);
+    /// ```
     pub fn update_imu(&mut self, time: time::Duration, imu: devices::ADIS) {
 
         // Copy of last state to use in integration
         let t_last = self.time;
         let a_last = self.acc_up;
         let v_last = self.vel_up;
+        let r_last = self.roll_rate;
 
         // Apply new data
         self.time = (time.as_secs() * 1000000000) + time.subsec_nanos() as u64;
@@ -78,17 +270,90 @@ impl State {
         // Subtract gravity!!!
         self.acc_up = imu.acc_x - 9.8;
 
-        // Compute and update integrals
-        let t_seconds = (self.time - t_last) as f64 / 1e9;
+        // The IMU's x axis is along the rocket, so that gyro is roll
+        self.roll_rate = imu.gyro_x;
+
+        // Compute and update integrals. With nothing to integrate from the
+        // new sample is just where we start.
+        let t_seconds = if self.integrating { self.time.saturating_sub(t_last) as f64 / 1e9 } else { 0.0 };
+        self.integrating = true;
         self.vel_up += (t_seconds * (self.acc_up + a_last)) / 2.0;
         self.altitude += (t_seconds * (self.vel_up + v_last)) / 2.0;
+        self.roll_angle += (t_seconds * (self.roll_rate + r_last)) / 2.0;
+
+        // Keep the roll angle between -180 and 180 degrees
+        self.roll_angle = (self.roll_angle + 180.0).rem_euclid(360.0) - 180.0;
+
+        // Smooth out vibration for finding flight phases
+        self.acc_filtered += t_seconds / (PHASE_FILTER + t_seconds) * (self.acc_up - self.acc_filtered);
+
+        // Move on to the next phase of the flight, once the condition for it
+        // has held for long enough
+        let since_launch = self.launch_time.map(|launch| self.time.saturating_sub(launch)).unwrap_or(0);
+        let (next, hold) = match self.phase {
+            Phase::Pad => (self.acc_filtered > LAUNCH_ACCELERATION, LAUNCH_HOLD),
+            Phase::Boost => (self.acc_filtered < 0.0 && since_launch >= MIN_BOOST_TIME, BURNOUT_HOLD),
+            Phase::Coast => (self.vel_up <= 0.0 && self.altitude - self.launch_altitude >= MIN_CLIMB, APOGEE_HOLD),
+            Phase::Descent => (false, 0),
+        };
+        if !next {
+            self.phase_pending = None;
+            return;
+        }
+        let since = *self.phase_pending.get_or_insert(self.time);
+        if self.time.saturating_sub(since) >= hold {
+            self.phase_pending = None;
+            self.phase = match self.phase {
+                Phase::Pad => {
+                    self.launch_time = Some(since);
+                    self.launch_altitude = self.altitude;
+                    Phase::Boost
+                },
+                Phase::Boost => Phase::Coast,
+                Phase::Coast | Phase::Descent => Phase::Descent,
+            };
+        }
+    }
+
+    /// Time since launch [s], or None if we haven't launched.
+    pub fn since_launch(&self) -> Option<f64> {
+        self.launch_time.map(|launch| self.time.saturating_sub(launch) as f64 / 1e9)
+    }
+
+    /// Read a state vector back from a message.
+    ///
+    /// This is the inverse of `as_message`.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **message_buffer**: A buffer of bytes at least as long as the STAT
+    ///   message (`SIZE_OF_STATE`).
+    ///
+    pub fn from_message(message_buffer: &[u8]) -> State {
+
+        let mut message = Cursor::new(message_buffer);
+
+        State {
+            time:       message.read_u64::<BigEndian>().unwrap(),
+            acc_up:     message.read_f64::<BigEndian>().unwrap(),
+            vel_up:     message.read_f64::<BigEndian>().unwrap(),
+            altitude:   message.read_f64::<BigEndian>().unwrap(),
+            roll_rate:  message.read_f64::<BigEndian>().unwrap(),
+            roll_angle: message.read_f64::<BigEndian>().unwrap(),
+            phase: Phase::Pad,
+            launch_time: None,
+            launch_altitude: 0.0,
+            acc_filtered: 0.0,
+            phase_pending: None,
+            integrating: false,
+        }
     }
 
     /// Return a copy of this struct as a byte array.
     ///
     /// The PSAS file and message type is always a byte array with big-endian
     /// representation of fields in a struct.
-    pub fn as_message(&mut self) -> [u8; SIZE_OF_STATE] {
+    pub fn as_message(&self) -> [u8; SIZE_OF_STATE] {
         let mut buffer = [0u8; SIZE_OF_STATE];
         {
             let mut message = Cursor::<&mut [u8]>::new(&mut buffer);
@@ -0,0 +1,351 @@
+/*! # Telemetry Policy
+
+The WiFi link to the ground is much slower than the Ethernet network inside the
+rocket, so we can't send everything we receive. Each message type has a
+policy that says whether to send it at all, how much to thin it out
+(decimation) and how important it is (priority).
+
+On top of that the whole link has a bandwidth budget, enforced with a token
+bucket. When a packet won't fit in the budget we drop the least important
+messages in it first, so things like the state vector and events still get
+through alongside a thinned out IMU stream.
+
+Every packet goes to each of a list of destinations, which can be plain
+(IPv4 or IPv6) addresses or multicast groups. That way several laptops on the
+ground can all listen to the same stream.
+*/
+
+extern crate libc;
+
+use std::ffi::CString;
+use std::io::Error;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::time;
+
+
+/// How important a message type is when the link is over budget.
+///
+/// Lower priority messages get dropped first. `Critical` messages are never
+/// dropped, even if that means going over budget.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+
+    /// First to go (bulk sensor data)
+    Low,
+
+    /// Most messages
+    Normal,
+
+    /// Last to go before critical messages
+    High,
+
+    /// Never dropped
+    Critical,
+}
+
+
+impl Priority {
+
+    /// Read a priority from its name (`low`, `normal`, `high` or `critical`).
+    pub fn from_name(name: &str) -> Option<Priority> {
+        match name {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            "critical" => Some(Priority::Critical),
+            _ => None,
+        }
+    }
+}
+
+
+/// Telemetry policy for a single message type.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::telemetry;
+///
+/// // Only send one in eight, and drop it first if we're short on bandwidth
+/// let policy = telemetry::Policy {
+///     enabled: true,
+///     decimation: 8,
+///     priority: telemetry::Priority::Low,
+/// };
+/// ```
+#[derive(Clone, Copy)]
+pub struct Policy {
+
+    /// Whether to send this message type at all
+    pub enabled: bool,
+
+    /// Send one out of every `decimation` messages
+    pub decimation: u32,
+
+    /// How important this message type is
+    pub priority: Priority,
+}
+
+
+/// Send everything, at normal priority.
+impl Default for Policy {
+    fn default () -> Policy {
+        Policy {
+            enabled: true,
+            decimation: 1,
+            priority: Priority::Normal,
+        }
+    }
+}
+
+
+/// A token bucket for limiting bandwidth.
+///
+/// The bucket fills with tokens (bytes) at a fixed rate, up to a maximum burst
+/// size. Sending a packet spends tokens.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::telemetry;
+/// use std::time::Duration;
+///
+/// // 1000 bytes/s, bursts of up to 500 bytes
+/// let mut bucket = telemetry::TokenBucket::new(1000, 500);
+///
+/// bucket.refill(Duration::from_millis(0));
+/// assert_eq!(bucket.available(), 500);
+///
+/// bucket.spend(500);
+/// bucket.refill(Duration::from_millis(100));
+/// assert_eq!(bucket.available(), 100);
+/// ```
+pub struct TokenBucket {
+
+    /// Fill rate [bytes/s]. Zero means unlimited.
+    rate: u64,
+
+    /// Most tokens the bucket can hold [bytes]
+    burst: u64,
+
+    /// Tokens currently in the bucket [bytes]. Can go negative if we had to
+    /// send critical messages over budget.
+    tokens: f64,
+
+    /// When we last refilled
+    last: Option<time::Duration>,
+}
+
+
+impl TokenBucket {
+
+    /// A new, full, token bucket.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **rate**: Fill rate [bytes/s], zero for unlimited
+    /// - **burst**: Most tokens the bucket can hold [bytes]
+    ///
+    pub fn new(rate: u64, burst: u64) -> TokenBucket {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst as f64,
+            last: None,
+        }
+    }
+
+    /// Add tokens for the time that has passed since the last refill.
+    pub fn refill(&mut self, now: time::Duration) {
+        if let Some(last) = self.last {
+            if now > last {
+                let dt = now - last;
+                let seconds = dt.as_secs() as f64 + (dt.subsec_nanos() as f64 / 1e9);
+                self.tokens = (self.tokens + (seconds * self.rate as f64)).min(self.burst as f64);
+            }
+        }
+        self.last = Some(now);
+    }
+
+    /// How many bytes we can send right now.
+    pub fn available(&self) -> usize {
+        if self.rate == 0 {
+            return usize::MAX;
+        }
+        self.tokens.max(0.0) as usize
+    }
+
+    /// Spend tokens on a packet.
+    pub fn spend(&mut self, bytes: usize) {
+        if self.rate > 0 {
+            self.tokens -= bytes as f64;
+        }
+    }
+}
+
+
+/// Which network interface to send multicast packets out of.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Interface {
+
+    /// The interface with this (IPv4) address
+    Address(Ipv4Addr),
+
+    /// The interface with this name (like `wlan0`)
+    Name(String),
+}
+
+
+/// Somewhere to send telemetry.
+///
+/// Destinations are written as an address and port, optionally followed by
+/// multicast settings:
+///
+/// - `ttl=N`: How many hops multicast packets may take (hop limit for IPv6)
+/// - `interface=IF`: Which interface to send multicast packets out of, either
+///   an interface name or, for IPv4, the address of the interface
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::telemetry;
+///
+/// let ground = telemetry::Destination::parse("192.168.1.20:35001").unwrap();
+/// let v6 = telemetry::Destination::parse("[fd00::1]:35001").unwrap();
+/// let group = telemetry::Destination::parse("239.255.0.1:35001 ttl=2 interface=wlan0").unwrap();
+///
+/// assert_eq!(group.ttl, Some(2));
+/// assert!(telemetry::Destination::parse("192.168.1.20:35001 ttl=2").is_err());
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct Destination {
+
+    /// Address and port to send to
+    pub addr: SocketAddr,
+
+    /// Multicast time to live (or IPv6 hop limit)
+    pub ttl: Option<u32>,
+
+    /// Interface to send multicast packets out of
+    pub interface: Option<Interface>,
+}
+
+
+impl Destination {
+
+    /// Read a destination from a string.
+    pub fn parse(value: &str) -> Result<Destination, String> {
+        let mut fields = value.split_whitespace();
+
+        let addr: SocketAddr = match fields.next().map(|addr| addr.parse()) {
+            Some(Ok(addr)) => addr,
+            _ => { return Err(format!("expected an address and port, got '{}'", value)); },
+        };
+
+        let mut destination = Destination { addr, ttl: None, interface: None };
+        for option in fields {
+            let mut split = option.splitn(2, '=');
+            match (split.next(), split.next()) {
+                (Some("ttl"), Some(ttl)) => {
+                    destination.ttl = match ttl.parse() {
+                        Ok(ttl) => Some(ttl),
+                        Err(_) => { return Err(format!("expected a number for ttl, got '{}'", ttl)); },
+                    };
+                },
+                (Some("interface"), Some(interface)) => {
+                    destination.interface = Some(match interface.parse() {
+                        Ok(address) => Interface::Address(address),
+                        Err(_) => Interface::Name(interface.to_string()),
+                    });
+                },
+                _ => { return Err(format!("unknown destination option '{}'", option)); },
+            }
+        }
+
+        if !addr.ip().is_multicast() && (destination.ttl.is_some() || destination.interface.is_some()) {
+            return Err(format!("ttl and interface are only for multicast destinations, got '{}'", value));
+        }
+
+        Ok(destination)
+    }
+
+    /// Open a socket for sending to this destination.
+    ///
+    /// For multicast groups this also sets up the time to live and outgoing
+    /// interface.
+    pub fn open(&self) -> Result<UdpSocket, Error> {
+        let socket = match self.addr {
+            SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+            SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+        };
+
+        if let Some(ttl) = self.ttl {
+            match self.addr.ip() {
+                IpAddr::V4(_) => socket.set_multicast_ttl_v4(ttl)?,
+                IpAddr::V6(_) => setsockopt(&socket, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_HOPS, ttl as libc::c_int)?,
+            }
+        }
+
+        if let Some(ref interface) = self.interface {
+            let index = match *interface {
+                Interface::Name(ref name) => interface_index(name)?,
+                Interface::Address(_) => 0,
+            };
+            match (self.addr.ip(), interface) {
+                (IpAddr::V4(_), &Interface::Address(address)) => {
+                    let request = libc::ip_mreqn {
+                        imr_multiaddr: libc::in_addr { s_addr: 0 },
+                        imr_address: libc::in_addr { s_addr: u32::from(address).to_be() },
+                        imr_ifindex: 0,
+                    };
+                    setsockopt(&socket, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, request)?;
+                },
+                (IpAddr::V4(_), _) => {
+                    let request = libc::ip_mreqn {
+                        imr_multiaddr: libc::in_addr { s_addr: 0 },
+                        imr_address: libc::in_addr { s_addr: 0 },
+                        imr_ifindex: index as libc::c_int,
+                    };
+                    setsockopt(&socket, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, request)?;
+                },
+                (IpAddr::V6(_), &Interface::Address(_)) => {
+                    return Err(Error::new(::std::io::ErrorKind::InvalidInput, "IPv6 multicast needs an interface name"));
+                },
+                (IpAddr::V6(_), _) => {
+                    setsockopt(&socket, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_IF, index as libc::c_int)?;
+                },
+            }
+        }
+
+        Ok(socket)
+    }
+}
+
+
+// Look up the index of a network interface by name
+fn interface_index(name: &str) -> Result<u32, Error> {
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => { return Err(Error::new(::std::io::ErrorKind::InvalidInput, "bad interface name")); },
+    };
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
+
+// Set a socket option that std doesn't have a function for
+fn setsockopt<T>(socket: &UdpSocket, level: libc::c_int, option: libc::c_int, value: T) -> Result<(), Error> {
+    let result = unsafe {
+        libc::setsockopt(socket.as_raw_fd(), level, option,
+                         &value as *const T as *const libc::c_void,
+                         mem::size_of::<T>() as libc::socklen_t)
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
@@ -0,0 +1,368 @@
+/*! # Transport
+
+Where the flight computer's data comes from and goes to.
+
+On the rocket, messages arrive over UDP, telemetry goes out over UDP and the
+log is a file on disk. The flight computer (`io::FC`) only talks to these
+through three small traits, so the same code can also run on in-memory
+versions of them. That lets us script the packets a test sends in and look at
+the exact bytes that come out, without binding real ports.
+
+ - A **`DatagramSource`** gives us incoming datagrams (a `UdpSocket`, a
+   `UdpListener` that waits on several sockets at once, or a `MemorySource`
+   with a queue of scripted packets)
+ - A **`TelemetrySink`** sends telemetry packets somewhere (a `UdpSink`, or a
+   `MemorySink` that keeps every packet)
+ - A **`LogSink`** stores the log (a `File`, or a `MemoryLog` that keeps the
+   bytes)
+
+The in-memory versions share their contents between clones, so keep a clone
+to look at after handing one to the flight computer.
+*/
+
+extern crate libc;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::rc::Rc;
+use std::time;
+
+
+/// A datagram and where it came from
+type Datagram = (SocketAddr, Vec<u8>);
+
+
+/// Somewhere datagrams come from.
+pub trait DatagramSource {
+
+    /// Wait for the next datagram.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **buffer**: Where to put the datagram. Anything that doesn't fit is
+    ///   lost.
+    /// - **timeout**: The longest to wait, or None to wait forever
+    ///
+    /// ## Returns:
+    ///
+    /// The size of the datagram and where it came from, or None if nothing
+    /// arrived in time.
+    fn recv(&mut self, buffer: &mut [u8], timeout: Option<time::Duration>) -> Result<Option<(usize, SocketAddr)>, Error>;
+}
+
+
+/// Somewhere telemetry packets go.
+pub trait TelemetrySink {
+
+    /// Send a whole telemetry packet.
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error>;
+}
+
+
+/// Somewhere the log goes.
+pub trait LogSink {
+
+    /// Append bytes to the log.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Make sure everything written so far is safely stored.
+    fn sync(&mut self) -> Result<(), Error>;
+}
+
+
+impl DatagramSource for UdpSocket {
+    fn recv(&mut self, buffer: &mut [u8], timeout: Option<time::Duration>) -> Result<Option<(usize, SocketAddr)>, Error> {
+        self.set_read_timeout(timeout)?;
+        match self.recv_from(buffer) {
+            Ok(received) => Ok(Some(received)),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::Interrupted => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+
+/// Listens on several UDP sockets at once.
+///
+/// All the sockets are non-blocking, and we wait for any of them to have
+/// something with `poll`. When more than one socket is ready they take turns,
+/// so a busy sensor can't starve a quiet command socket.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::transport::{self, DatagramSource};
+/// use std::net::UdpSocket;
+/// use std::time::Duration;
+///
+/// let mut listener = transport::UdpListener::bind(&["127.0.0.1:0".parse().unwrap(),
+///                                                  "127.0.0.1:0".parse().unwrap()]).unwrap();
+/// let addrs = listener.local_addrs().unwrap();
+///
+/// let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// sender.send_to(&[1], addrs[1]).unwrap();
+///
+/// let mut buffer = [0u8; 16];
+/// let (size, from) = listener.recv(&mut buffer, Some(Duration::from_secs(1))).unwrap().unwrap();
+/// assert_eq!((size, buffer[0]), (1, 1));
+/// assert_eq!(from, sender.local_addr().unwrap());
+///
+/// // Nothing else is coming
+/// assert!(listener.recv(&mut buffer, Some(Duration::from_millis(1))).unwrap().is_none());
+/// ```
+pub struct UdpListener {
+
+    /// Sockets we're listening on
+    sockets: Vec<UdpSocket>,
+
+    /// Sockets `poll` said were ready that we haven't read from yet
+    ready: VecDeque<usize>,
+}
+
+
+impl UdpListener {
+
+    /// Open a socket for each address.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **addrs**: Where to listen
+    ///
+    pub fn bind(addrs: &[SocketAddr]) -> Result<UdpListener, Error> {
+        let mut sockets = Vec::new();
+        for addr in addrs {
+            let socket = UdpSocket::bind(addr)?;
+            socket.set_nonblocking(true)?;
+            sockets.push(socket);
+        }
+        Ok(UdpListener { sockets, ready: VecDeque::new() })
+    }
+
+    /// The address each socket is bound to, in the order they were given to
+    /// `bind`.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, Error> {
+        self.sockets.iter().map(|socket| socket.local_addr()).collect()
+    }
+
+    // Wait for sockets to be ready, and queue up the ones that are
+    fn poll(&mut self, timeout: Option<time::Duration>) -> Result<(), Error> {
+        let mut fds: Vec<libc::pollfd> = self.sockets.iter().map(|socket| libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }).collect();
+
+        // Round up to whole milliseconds so we don't wake up just early
+        let timeout = match timeout {
+            Some(timeout) => {
+                let millis = timeout.as_secs() * 1000 + (timeout.subsec_nanos() as u64).div_ceil(1000000);
+                millis.min(libc::c_int::MAX as u64) as libc::c_int
+            },
+            None => -1,
+        };
+
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready < 0 {
+            return Err(Error::last_os_error());
+        }
+        for (index, fd) in fds.iter().enumerate() {
+            if fd.revents != 0 {
+                self.ready.push_back(index);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl DatagramSource for UdpListener {
+    fn recv(&mut self, buffer: &mut [u8], timeout: Option<time::Duration>) -> Result<Option<(usize, SocketAddr)>, Error> {
+        if self.ready.is_empty() {
+            match self.poll(timeout) {
+                Ok(()) => { },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => { return Ok(None); },
+                Err(e) => { return Err(e); },
+            }
+        }
+
+        while let Some(index) = self.ready.pop_front() {
+            match self.sockets[index].recv_from(buffer) {
+                Ok(received) => { return Ok(Some(received)); },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => { },
+                Err(e) => { return Err(e); },
+            }
+        }
+        Ok(None)
+    }
+}
+
+
+/// Telemetry over UDP to a single destination.
+pub struct UdpSink {
+
+    /// Where to send packets
+    pub addr: SocketAddr,
+
+    /// Socket to send them from
+    pub socket: UdpSocket,
+}
+
+
+impl TelemetrySink for UdpSink {
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        self.socket.send_to(packet, self.addr)?;
+        Ok(())
+    }
+}
+
+
+impl LogSink for File {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.write_all(bytes)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.sync_all()
+    }
+}
+
+
+/// Scripted datagrams.
+///
+/// Datagrams come out in the order they were pushed. When the queue is empty
+/// `recv` returns straight away with nothing, as if it timed out.
+///
+/// # Example
+///
+/// ```
+/// use rust_fc::transport::{self, DatagramSource};
+///
+/// let mut source = transport::MemorySource::new();
+/// source.push("127.0.0.1:35020".parse().unwrap(), &[0, 0, 0, 1]);
+///
+/// let mut buffer = [0u8; 16];
+/// let (size, from) = source.recv(&mut buffer, None).unwrap().unwrap();
+/// assert_eq!(size, 4);
+/// assert_eq!(from.port(), 35020);
+/// assert!(source.recv(&mut buffer, None).unwrap().is_none());
+/// ```
+#[derive(Clone, Default)]
+pub struct MemorySource {
+
+    /// Datagrams waiting to be received, and where they're from
+    queue: Rc<RefCell<VecDeque<Datagram>>>,
+}
+
+
+impl MemorySource {
+
+    /// An empty source.
+    pub fn new() -> MemorySource {
+        Default::default()
+    }
+
+    /// Queue up a datagram.
+    ///
+    /// ## Parameters:
+    ///
+    /// - **from**: Where the datagram was sent from
+    /// - **datagram**: The bytes of the datagram
+    ///
+    pub fn push(&self, from: SocketAddr, datagram: &[u8]) {
+        self.queue.borrow_mut().push_back((from, datagram.to_vec()));
+    }
+
+    /// Number of datagrams still waiting.
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// True if there are no datagrams waiting.
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+}
+
+
+impl DatagramSource for MemorySource {
+    fn recv(&mut self, buffer: &mut [u8], _timeout: Option<time::Duration>) -> Result<Option<(usize, SocketAddr)>, Error> {
+        match self.queue.borrow_mut().pop_front() {
+            Some((from, datagram)) => {
+                let size = datagram.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&datagram[..size]);
+                Ok(Some((size, from)))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+
+/// Keeps every telemetry packet sent.
+#[derive(Clone, Default)]
+pub struct MemorySink {
+
+    /// Packets in the order they were sent
+    packets: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+
+impl MemorySink {
+
+    /// An empty sink.
+    pub fn new() -> MemorySink {
+        Default::default()
+    }
+
+    /// A copy of every packet sent so far.
+    pub fn packets(&self) -> Vec<Vec<u8>> {
+        self.packets.borrow().clone()
+    }
+}
+
+
+impl TelemetrySink for MemorySink {
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        self.packets.borrow_mut().push(packet.to_vec());
+        Ok(())
+    }
+}
+
+
+/// Keeps the log in memory.
+#[derive(Clone, Default)]
+pub struct MemoryLog {
+
+    /// Everything written to the log
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+
+impl MemoryLog {
+
+    /// An empty log.
+    pub fn new() -> MemoryLog {
+        Default::default()
+    }
+
+    /// A copy of everything logged so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.borrow().clone()
+    }
+}
+
+
+impl LogSink for MemoryLog {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.bytes.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}